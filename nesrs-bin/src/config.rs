@@ -0,0 +1,71 @@
+use nesrs::palette::{Palette, PalettePreset};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+const CONFIG_PATH: &str = "nesrs.yaml";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum PaletteSetting {
+    Preset(PalettePreset),
+    File(PathBuf),
+}
+
+impl PaletteSetting {
+    pub(crate) fn try_load(&self) -> Result<Palette, String> {
+        match self {
+            PaletteSetting::Preset(preset) => Ok(Palette::from_preset(*preset)),
+            PaletteSetting::File(path) => fs::read(path)
+                .map_err(|error| error.to_string())
+                .and_then(|bytes| Palette::from_pal(&bytes))
+                .map_err(|error| format!("cannot load palette {:?}: {}", path, error)),
+        }
+    }
+
+    pub(crate) fn load(&self) -> Palette {
+        match self.try_load() {
+            Ok(palette) => palette,
+            Err(error) => {
+                println!("ERROR: {}", error);
+                Palette::new()
+            }
+        }
+    }
+}
+
+/// Frontend settings that are kept between runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Config {
+    pub palette: PaletteSetting,
+    /// Draw every sprite on a scanline, only affects the picture
+    pub no_sprite_limit: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            palette: PaletteSetting::Preset(PalettePreset::default()),
+            no_sprite_limit: false,
+        }
+    }
+}
+
+impl Config {
+    pub(crate) fn load() -> Self {
+        fs::read_to_string(CONFIG_PATH)
+            .ok()
+            .and_then(|content| serde_yaml::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn save(&self) {
+        let result = serde_yaml::to_string(self)
+            .map_err(|error| error.to_string())
+            .and_then(|content| fs::write(CONFIG_PATH, content).map_err(|error| error.to_string()));
+
+        if let Err(error) = result {
+            println!("ERROR: cannot save config: {}", error);
+        }
+    }
+}
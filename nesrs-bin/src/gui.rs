@@ -1,7 +1,13 @@
-use egui::{ClippedMesh, FontDefinitions};
+use crate::config::{Config, PaletteSetting};
+use egui::{Align2, ClippedMesh, Color32, FontDefinitions, Sense, TextStyle, TextureId};
 use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
 use egui_winit_platform::{Platform, PlatformDescriptor};
+use epi::TextureAllocator;
 use native_dialog::FileDialog;
+use nesrs::bus::Bus;
+use nesrs::palette::PalettePreset;
+use nesrs::ppu::{NametableTileInfo, PPUEvent, PPUEventKind, RenderDebug, SpriteInfo};
+use nesrs::utils::Screen;
 use pixels::{wgpu, PixelsContext};
 use std::path::PathBuf;
 use std::time::Instant;
@@ -19,11 +25,42 @@ pub(crate) struct Gui {
     window_open: bool,
     pub opened_fname: Option<PathBuf>,
     pub do_reset: bool,
+    pub config: Config,
+    pub palette_changed: bool,
+    pub config_changed: bool,
+    pub render_debug: RenderDebug,
+
+    // Debug windows
+    nametable_window_open: bool,
+    nametable_texture: Option<TextureId>,
+    nametable_hover: Option<(usize, usize)>,
+    nametable_tile_info: Option<NametableTileInfo>,
+    sprites_window_open: bool,
+    sprites_texture: Option<TextureId>,
+    sprites: Vec<SpriteInfo>,
+    palettes_window_open: bool,
+    palette_ram: Vec<u8>,
+    nes_colors: Vec<Color32>,
+    palette_selected: Option<usize>,
+    palette_write: Option<(usize, u8)>,
+    patterns_window_open: bool,
+    pattern_textures: [Option<TextureId>; 2],
+    pattern_palette: usize,
+    events_window_open: bool,
+    events_texture: Option<(TextureId, usize)>,
+    events_hover: Option<(usize, usize)>,
+    hovered_event: Option<PPUEvent>,
 }
 
 impl Gui {
     /// Create egui.
-    pub(crate) fn new(width: u32, height: u32, scale_factor: f64, context: &PixelsContext) -> Self {
+    pub(crate) fn new(
+        width: u32,
+        height: u32,
+        scale_factor: f64,
+        context: &PixelsContext,
+        config: Config,
+    ) -> Self {
         let platform = Platform::new(PlatformDescriptor {
             physical_width: width,
             physical_height: height,
@@ -47,6 +84,29 @@ impl Gui {
             window_open: false,
             opened_fname: None,
             do_reset: true,
+            config,
+            palette_changed: false,
+            config_changed: false,
+            render_debug: RenderDebug::default(),
+            nametable_window_open: false,
+            nametable_texture: None,
+            nametable_hover: None,
+            nametable_tile_info: None,
+            sprites_window_open: false,
+            sprites_texture: None,
+            sprites: Vec::new(),
+            palettes_window_open: false,
+            palette_ram: Vec::new(),
+            nes_colors: Vec::new(),
+            palette_selected: None,
+            palette_write: None,
+            patterns_window_open: false,
+            pattern_textures: [None, None],
+            pattern_palette: 0,
+            events_window_open: false,
+            events_texture: None,
+            events_hover: None,
+            hovered_event: None,
         }
     }
 
@@ -69,7 +129,11 @@ impl Gui {
     }
 
     /// Prepare egui.
-    pub(crate) fn prepare(&mut self) {
+    pub(crate) fn prepare(&mut self, nes: Option<&mut Bus>) {
+        if let Some(bus) = nes {
+            self.update_debug_textures(bus);
+        }
+
         self.platform
             .update_time(self.start_time.elapsed().as_secs_f64());
 
@@ -84,8 +148,88 @@ impl Gui {
         self.paint_jobs = self.platform.context().tessellate(paint_commands);
     }
 
+    /// Refresh the images of the open debug windows.
+    fn update_debug_textures(&mut self, bus: &mut Bus) {
+        let mut ppu = bus.ppu.lock().unwrap();
+
+        if self.nametable_window_open {
+            ppu.set_debug_nametable_screen();
+            self.nametable_texture =
+                Some(self.upload_screen(self.nametable_texture, &ppu.screen_debug_nametable));
+            self.nametable_tile_info = self
+                .nametable_hover
+                .map(|(x, y)| ppu.debug_nametable_tile_info(x, y));
+        }
+
+        if self.sprites_window_open {
+            ppu.set_debug_sprites_screen();
+            self.sprites_texture =
+                Some(self.upload_screen(self.sprites_texture, &ppu.screen_debug_sprites));
+            self.sprites = (0..64).map(|index| ppu.debug_sprite_info(index)).collect();
+        }
+
+        // CHR-RAM can change at any time, so refresh on every frame
+        if self.patterns_window_open {
+            for table in 0..2 {
+                ppu.set_debug_pattern_screen(table, self.pattern_palette);
+                self.pattern_textures[table] = Some(self.upload_screen(
+                    self.pattern_textures[table],
+                    &ppu.screen_debug_pattern[table],
+                ));
+            }
+        }
+
+        if ppu.event_recording() != self.events_window_open {
+            ppu.set_event_recording(self.events_window_open);
+        }
+
+        if self.events_window_open {
+            ppu.set_debug_events_screen();
+            let previous = self.events_texture.map(|(texture, _)| texture);
+            let texture = self.upload_screen(previous, &ppu.screen_debug_events);
+            self.events_texture = Some((texture, ppu.screen_debug_events.height()));
+            self.hovered_event = self
+                .events_hover
+                .and_then(|(x, y)| ppu.debug_event_at(x, y));
+        }
+
+        if let Some((index, value)) = self.palette_write.take() {
+            ppu.debug_write_palette(index, value);
+        }
+
+        if self.palettes_window_open {
+            self.palette_ram = (0..32).map(|index| ppu.debug_read_palette(index)).collect();
+            self.nes_colors = (0..64)
+                .map(|index| {
+                    let (r, g, b) = ppu.palette().color(index);
+                    Color32::from_rgb(r, g, b)
+                })
+                .collect();
+        }
+    }
+
+    /// Replace a user texture with the content of a screen.
+    fn upload_screen(&mut self, previous: Option<TextureId>, screen: &Screen) -> TextureId {
+        if let Some(texture) = previous {
+            self.rpass.free(texture);
+        }
+
+        let pixels: Vec<Color32> = screen
+            .image()
+            .chunks_exact(4)
+            .map(|pixel| Color32::from_rgba_premultiplied(pixel[0], pixel[1], pixel[2], pixel[3]))
+            .collect();
+
+        self.rpass
+            .alloc_srgba_premultiplied((screen.width(), screen.height()), &pixels)
+    }
+
     /// Create the UI using egui.
     fn ui(&mut self, ctx: &egui::CtxRef) {
+        self.palettes_window(ctx);
+        self.patterns_window(ctx);
+        self.events_window(ctx);
+
         egui::TopBottomPanel::top("menubar_container").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 egui::menu::menu(ui, "File", |ui| {
@@ -106,9 +250,139 @@ impl Gui {
                         self.do_reset = true;
                     }
                 });
+
+                egui::menu::menu(ui, "Video", |ui| {
+                    ui.label("Palette");
+
+                    for preset in PalettePreset::ALL.iter() {
+                        let setting = PaletteSetting::Preset(*preset);
+                        let checked = self.config.palette == setting;
+
+                        if ui.radio(checked, preset.name()).clicked() {
+                            self.config.palette = setting;
+                            self.palette_changed = true;
+                        }
+                    }
+
+                    let is_file = matches!(self.config.palette, PaletteSetting::File(_));
+
+                    if ui.radio(is_file, "Load .pal...").clicked() {
+                        let path = FileDialog::new()
+                            .add_filter("NES palette", &["pal"])
+                            .show_open_single_file()
+                            .unwrap();
+
+                        // only a file that parses gets selected and saved
+                        if let Some(path) = path {
+                            let setting = PaletteSetting::File(path);
+
+                            match setting.try_load() {
+                                Ok(_) => {
+                                    self.config.palette = setting;
+                                    self.palette_changed = true;
+                                }
+                                Err(error) => println!("ERROR: {}", error),
+                            }
+                        }
+                    }
+
+                    ui.separator();
+
+                    if ui
+                        .checkbox(&mut self.config.no_sprite_limit, "No sprite limit")
+                        .changed()
+                    {
+                        self.config_changed = true;
+                    }
+
+                    ui.separator();
+                    ui.label("Debug");
+
+                    let debug = &mut self.render_debug;
+                    ui.checkbox(&mut debug.hide_background, "Hide background");
+                    ui.checkbox(&mut debug.hide_sprites, "Hide sprites");
+                    ui.checkbox(&mut debug.show_left_column, "Always show left column");
+                    ui.checkbox(&mut debug.sprite0_only, "Sprite 0 only");
+                    ui.checkbox(&mut debug.highlight_sprite0_hit, "Highlight sprite 0 hit");
+                    ui.checkbox(&mut debug.sprite_boxes, "Sprite boxes");
+                });
+
+                egui::menu::menu(ui, "Debug", |ui| {
+                    ui.checkbox(&mut self.nametable_window_open, "Nametables");
+                    ui.checkbox(&mut self.sprites_window_open, "Sprites");
+                    ui.checkbox(&mut self.palettes_window_open, "Palettes");
+                    ui.checkbox(&mut self.patterns_window_open, "Pattern tables");
+                    ui.checkbox(&mut self.events_window_open, "PPU events");
+                });
             });
         });
 
+        if let Some(texture) = self.nametable_texture {
+            let tile_info = self.nametable_tile_info;
+            let mut hover = None;
+
+            egui::Window::new("Nametables")
+                .open(&mut self.nametable_window_open)
+                .show(ctx, |ui| {
+                    let response = ui.image(texture, egui::vec2(512.0, 480.0));
+
+                    if let Some(pos) = response.hover_pos() {
+                        let pos = pos - response.rect.min;
+                        hover = Some(((pos.x as usize).min(511), (pos.y as usize).min(479)));
+                    }
+
+                    match tile_info {
+                        Some(info) => {
+                            ui.label(format!(
+                                "Nametable {} tile ({}, {}): ${:02X} at ${:04X}",
+                                info.nametable,
+                                info.tile_x,
+                                info.tile_y,
+                                info.tile_id,
+                                info.address
+                            ));
+                            ui.label(format!(
+                                "Attribute ${:02X} at ${:04X}, palette {}",
+                                info.attribute, info.attribute_address, info.palette
+                            ));
+                        }
+                        None => {
+                            ui.label("Hover a tile to inspect it");
+                        }
+                    }
+                });
+
+            self.nametable_hover = hover;
+        }
+
+        if let Some(texture) = self.sprites_texture {
+            let sprites = &self.sprites;
+
+            egui::Window::new("Sprites")
+                .open(&mut self.sprites_window_open)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.image(texture, egui::vec2(128.0, 256.0));
+
+                        egui::ScrollArea::from_max_height(256.0).show(ui, |ui| {
+                            for sprite in sprites.iter() {
+                                ui.monospace(format!(
+                                    "#{:02} X:{:3} Y:{:3} T:${:02X} P:{} {}{}{}",
+                                    sprite.index,
+                                    sprite.x,
+                                    sprite.y,
+                                    sprite.tile,
+                                    sprite.palette,
+                                    if sprite.behind_background { "B" } else { "-" },
+                                    if sprite.flip_horizontal { "H" } else { "-" },
+                                    if sprite.flip_vertical { "V" } else { "-" },
+                                ));
+                            }
+                        });
+                    });
+                });
+        }
+
         egui::Window::new("Hello, egui!")
             .open(&mut self.window_open)
             .show(ctx, |ui| {
@@ -132,6 +406,177 @@ impl Gui {
             });
     }
 
+    /// Show the 32 palette RAM entries, and the 64 NES colors to pick
+    /// from once an entry is selected.
+    fn palettes_window(&mut self, ctx: &egui::CtxRef) {
+        if self.palette_ram.len() < 32 || self.nes_colors.len() < 64 {
+            return;
+        }
+
+        let palette_ram = &self.palette_ram;
+        let nes_colors = &self.nes_colors;
+        let mut selected = self.palette_selected;
+        let mut write = None;
+
+        egui::Window::new("Palettes")
+            .open(&mut self.palettes_window_open)
+            .show(ctx, |ui| {
+                for palette in 0..8 {
+                    ui.horizontal(|ui| {
+                        let name = if palette < 4 { "BG" } else { "SPR" };
+                        ui.monospace(format!("{:3} {}", name, palette & 0x03));
+
+                        for entry in 0..4 {
+                            let index = (palette << 2) | entry;
+                            let value = palette_ram[index];
+                            let response = color_swatch(
+                                ui,
+                                nes_colors[value as usize],
+                                value,
+                                selected == Some(index),
+                            );
+
+                            if response.clicked() {
+                                selected = Some(index);
+                            }
+                        }
+                    });
+                }
+
+                if let Some(index) = selected {
+                    ui.separator();
+                    ui.label(format!("Pick a color for ${:04X}", 0x3f00 + index));
+
+                    for row in 0..4 {
+                        ui.horizontal(|ui| {
+                            for col in 0..16 {
+                                let value = (row << 4) | col;
+                                let is_current = palette_ram[index] == value;
+                                let response =
+                                    color_swatch(ui, nes_colors[value as usize], value, is_current);
+
+                                if response.clicked() {
+                                    write = Some((index, value));
+                                }
+                            }
+                        });
+                    }
+                }
+            });
+
+        self.palette_selected = selected;
+
+        if write.is_some() {
+            self.palette_write = write;
+        }
+    }
+
+    /// Show both pattern tables with the selected palette.
+    fn patterns_window(&mut self, ctx: &egui::CtxRef) {
+        let textures = match self.pattern_textures {
+            [Some(left), Some(right)] => [left, right],
+            _ => return,
+        };
+
+        let palette = &mut self.pattern_palette;
+
+        egui::Window::new("Pattern tables")
+            .open(&mut self.patterns_window_open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Palette");
+
+                    for index in 0..8 {
+                        let name = if index < 4 {
+                            format!("BG {}", index)
+                        } else {
+                            format!("SPR {}", index & 0x03)
+                        };
+
+                        ui.radio_value(palette, index, name);
+                    }
+                });
+
+                let mut hover = None;
+
+                ui.horizontal(|ui| {
+                    for (table, texture) in textures.iter().enumerate() {
+                        let response = ui.image(*texture, egui::vec2(256.0, 256.0));
+
+                        if let Some(pos) = response.hover_pos() {
+                            let pos = (pos - response.rect.min) / 2.0;
+                            let tile_x = (pos.x as usize).min(127) / 8;
+                            let tile_y = (pos.y as usize).min(127) / 8;
+                            hover = Some((table, (tile_y << 4) | tile_x));
+                        }
+                    }
+                });
+
+                match hover {
+                    Some((table, tile)) => {
+                        ui.label(format!(
+                            "Table {} tile ${:02X} at CHR ${:04X}",
+                            table,
+                            tile,
+                            (table << 12) | (tile << 4)
+                        ));
+                    }
+                    None => {
+                        ui.label("Hover a tile to inspect it");
+                    }
+                }
+            });
+    }
+
+    /// Show the PPU events of the last frame by scanline and dot.
+    fn events_window(&mut self, ctx: &egui::CtxRef) {
+        let (texture, rows) = match self.events_texture {
+            Some(texture) => texture,
+            None => return,
+        };
+
+        let hovered_event = self.hovered_event;
+        let mut hover = None;
+
+        egui::Window::new("PPU events")
+            .open(&mut self.events_window_open)
+            .show(ctx, |ui| {
+                let response = ui.image(texture, egui::vec2(682.0, rows as f32 * 2.0));
+
+                if let Some(pos) = response.hover_pos() {
+                    let pos = (pos - response.rect.min) / 2.0;
+                    hover = Some(((pos.x as usize).min(340), (pos.y as usize).min(rows - 1)));
+                }
+
+                match hovered_event {
+                    Some(event) => {
+                        let action = match event.kind {
+                            PPUEventKind::RegisterRead => "Read",
+                            PPUEventKind::RegisterWrite => "Write",
+                            PPUEventKind::OamDma => "OAM DMA",
+                            PPUEventKind::Nmi => "NMI",
+                            PPUEventKind::MapperIrq => "Mapper IRQ",
+                        };
+
+                        ui.label(format!(
+                            "{} ${:04X} = ${:02X} at scanline {}, dot {} (PC ${:04X})",
+                            action,
+                            event.address,
+                            event.value,
+                            event.scanline,
+                            event.cycle,
+                            event.pc
+                        ));
+                    }
+                    None => {
+                        ui.label("Hover an event to inspect it");
+                    }
+                }
+            });
+
+        self.events_hover = hover;
+    }
+
     /// Render egui.
     pub(crate) fn render(
         &mut self,
@@ -164,3 +609,28 @@ impl Gui {
         );
     }
 }
+
+/// A clickable square of a NES color labelled with its index.
+fn color_swatch(ui: &mut egui::Ui, color: Color32, value: u8, highlight: bool) -> egui::Response {
+    let (rect, response) = ui.allocate_exact_size(egui::vec2(24.0, 24.0), Sense::click());
+    let text_color = if color.r() as u32 + color.g() as u32 + color.b() as u32 > 384 {
+        Color32::BLACK
+    } else {
+        Color32::WHITE
+    };
+
+    ui.painter().rect_filled(rect, 0.0, color);
+    ui.painter().text(
+        rect.center(),
+        Align2::CENTER_CENTER,
+        format!("{:02X}", value),
+        TextStyle::Small,
+        text_color,
+    );
+
+    if highlight {
+        ui.painter().rect_stroke(rect, 0.0, (2.0, Color32::YELLOW));
+    }
+
+    response
+}
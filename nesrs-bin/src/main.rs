@@ -1,6 +1,7 @@
 #![deny(clippy::all)]
 #![forbid(unsafe_code)]
 
+use crate::config::Config;
 use crate::gui::Gui;
 use log::error;
 use pixels::{Error, Pixels, SurfaceTexture};
@@ -18,6 +19,7 @@ use nesrs::ppu::{NES_HEIGHT_SIZE, NES_WIDTH_SIZE};
 use std::fs::File;
 use std::io::prelude::*;
 
+mod config;
 mod gui;
 
 fn main() -> Result<(), Error> {
@@ -48,11 +50,13 @@ fn main() -> Result<(), Error> {
             window_size.height,
             scale_factor,
             pixels.context(),
+            Config::load(),
         );
 
         (pixels, gui)
     };
     let mut nes = None;
+    let mut palette = gui.config.palette.load();
 
     event_loop.run(move |event, _, control_flow| {
         // Update egui inputs
@@ -78,12 +82,29 @@ fn main() -> Result<(), Error> {
                     println!("{}", string);
                 }
 
+                bus.ppu.lock().unwrap().set_palette(palette.clone());
                 bus.reset();
             }
 
             gui.opened_fname = None;
         }
 
+        if gui.palette_changed {
+            palette = gui.config.palette.load();
+
+            if let Some(bus) = nes.as_mut() {
+                bus.ppu.lock().unwrap().set_palette(palette.clone());
+            }
+
+            gui.config.save();
+            gui.palette_changed = false;
+        }
+
+        if gui.config_changed {
+            gui.config.save();
+            gui.config_changed = false;
+        }
+
         if gui.do_reset {
             if let Some(bus) = nes.as_mut() {
                 bus.reset();
@@ -97,13 +118,19 @@ fn main() -> Result<(), Error> {
             // Draw the world
             // world.draw(pixels.get_frame());
             if let Some(bus) = &mut nes {
+                {
+                    let mut ppu = bus.ppu.lock().unwrap();
+                    ppu.set_no_sprite_limit(gui.config.no_sprite_limit);
+                    ppu.set_render_debug(gui.render_debug);
+                }
+
                 bus.clock_until_frame_done();
                 let ppu = bus.ppu.lock().unwrap();
                 ppu.screen().copy_to(pixels.get_frame());
             }
 
             // Prepare egui
-            gui.prepare();
+            gui.prepare(nes.as_mut());
 
             // Render everything together
             let render_result = pixels.render_with(|encoder, render_target, context| {
@@ -0,0 +1,276 @@
+use crate::region::Region;
+
+// CPU cycles where the frame counter steps, the last one ending the
+// sequence. 4-step mode sets the IRQ flag on the last three.
+const FOUR_STEP_CYCLES_NTSC: [u32; 6] = [7457, 14913, 22371, 29828, 29829, 29830];
+const FIVE_STEP_CYCLES_NTSC: [u32; 6] = [7457, 14913, 22371, 29829, 37281, 37282];
+const FOUR_STEP_CYCLES_PAL: [u32; 6] = [8313, 16627, 24939, 33252, 33253, 33254];
+const FIVE_STEP_CYCLES_PAL: [u32; 6] = [8313, 16627, 24939, 33253, 41565, 41566];
+
+// CPU cycles between two DMC output bits, by rate index
+const DMC_RATES_NTSC: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const DMC_RATES_PAL: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+/// The DMC sample reader. Nothing is played, but the sample bytes are
+/// fetched through DMA at the pace the output unit consumes them.
+struct Dmc {
+    irq_enabled: bool,
+    loop_sample: bool,
+    rate_index: usize,
+    timer: u16,
+    // bits left in the current output cycle
+    bits_remaining: u8,
+
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    // a DMA was asked for the byte at `current_address`
+    dma_pending: bool,
+    irq: bool,
+}
+
+impl Dmc {
+    fn new() -> Self {
+        Dmc {
+            irq_enabled: false,
+            loop_sample: false,
+            rate_index: 0,
+            timer: DMC_RATES_NTSC[0],
+            bits_remaining: 8,
+            sample_address: 0xc000,
+            sample_length: 1,
+            current_address: 0xc000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            dma_pending: false,
+            irq: false,
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    fn clock(&mut self, rates: &[u16; 16]) {
+        self.timer -= 1;
+        if self.timer == 0 {
+            self.timer = rates[self.rate_index];
+
+            // a new output cycle empties the sample buffer
+            self.bits_remaining -= 1;
+            if self.bits_remaining == 0 {
+                self.bits_remaining = 8;
+                self.sample_buffer = None;
+            }
+        }
+    }
+
+    // The address to fetch the next sample byte from, once per byte
+    fn take_dma_request(&mut self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 && !self.dma_pending {
+            self.dma_pending = true;
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    fn fill(&mut self, sample: u8) {
+        self.sample_buffer = Some(sample);
+        self.dma_pending = false;
+
+        // the address wraps to $8000
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.loop_sample {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    fn write(&mut self, address: usize, value: u8) {
+        match address {
+            0x4010 => {
+                self.irq_enabled = value & 0x80 != 0;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+                self.loop_sample = value & 0x40 != 0;
+                self.rate_index = (value & 0x0f) as usize;
+            }
+            0x4012 => self.sample_address = 0xc000 | (value as u16) << 6,
+            0x4013 => self.sample_length = (value as u16) << 4 | 1,
+            0x4015 => {
+                self.irq = false;
+                if value & 0x10 == 0 {
+                    self.bytes_remaining = 0;
+                } else if self.bytes_remaining == 0 {
+                    self.restart();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The parts of the 2A03 APU the rest of the console depends on. Only
+/// the frame counter and the DMC sample fetches are there, with their
+/// IRQs, no sound is generated.
+pub struct Apu {
+    region: Region,
+    // CPU cycle the APU was last clocked at
+    cycle: u64,
+
+    // CPU cycles into the frame counter sequence
+    frame_cycle: u32,
+    five_step_mode: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    // $4017 value and the CPU cycles left before the sequence restarts
+    frame_write: Option<(u8, u32)>,
+
+    dmc: Dmc,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu {
+            region: Region::default(),
+            cycle: 0,
+            frame_cycle: 0,
+            five_step_mode: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_write: None,
+            dmc: Dmc::new(),
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// The frame counter restarts as if $4017 got its last value again,
+    /// and the DMC is stopped as by a $4015 write of 0
+    pub fn reset(&mut self) {
+        let value = (self.five_step_mode as u8) << 7 | (self.irq_inhibit as u8) << 6;
+        self.frame_irq = false;
+        self.write(0x4017, value);
+        self.write(0x4015, 0x00);
+    }
+
+    /// Run one CPU cycle
+    pub fn clock(&mut self, cycle: u64) {
+        self.cycle = cycle;
+
+        if let Some((value, delay)) = self.frame_write {
+            if delay > 1 {
+                self.frame_write = Some((value, delay - 1));
+            } else {
+                self.frame_write = None;
+                self.five_step_mode = value & 0x80 != 0;
+                self.frame_cycle = 0;
+            }
+        }
+
+        self.frame_cycle += 1;
+
+        let steps = self.frame_steps();
+        if !self.five_step_mode && !self.irq_inhibit && self.frame_cycle >= steps[3] {
+            self.frame_irq = true;
+        }
+        if self.frame_cycle == steps[5] {
+            self.frame_cycle = 0;
+        }
+
+        let rates = self.dmc_rates();
+        self.dmc.clock(rates);
+    }
+
+    fn frame_steps(&self) -> [u32; 6] {
+        match (self.region, self.five_step_mode) {
+            (Region::Pal, false) => FOUR_STEP_CYCLES_PAL,
+            (Region::Pal, true) => FIVE_STEP_CYCLES_PAL,
+            (_, false) => FOUR_STEP_CYCLES_NTSC,
+            (_, true) => FIVE_STEP_CYCLES_NTSC,
+        }
+    }
+
+    fn dmc_rates(&self) -> &'static [u16; 16] {
+        match self.region {
+            Region::Pal => &DMC_RATES_PAL,
+            _ => &DMC_RATES_NTSC,
+        }
+    }
+
+    /// Whether the frame counter holds the IRQ line
+    pub fn frame_irq(&self) -> bool {
+        self.frame_irq
+    }
+
+    /// Whether the DMC holds the IRQ line
+    pub fn dmc_irq(&self) -> bool {
+        self.dmc.irq
+    }
+
+    /// The address of a sample byte the DMC needs a DMA for. It is asked
+    /// once, the byte comes back through `fill_dmc_sample`.
+    pub fn take_dmc_dma_request(&mut self) -> Option<u16> {
+        self.dmc.take_dma_request()
+    }
+
+    /// Whether the DMC waits for the byte of a DMA
+    pub fn is_dmc_dma_pending(&self) -> bool {
+        self.dmc.dma_pending
+    }
+
+    /// Hand the byte fetched by the DMC DMA over
+    pub fn fill_dmc_sample(&mut self, sample: u8) {
+        self.dmc.fill(sample);
+    }
+
+    /// Read $4015. Reading clears the frame IRQ flag.
+    pub fn read_status(&mut self, is_read_only: bool) -> u8 {
+        let status = (self.dmc.irq as u8) << 7
+            | (self.frame_irq as u8) << 6
+            | ((self.dmc.bytes_remaining > 0) as u8) << 4;
+        if !is_read_only {
+            self.frame_irq = false;
+        }
+
+        status
+    }
+
+    /// Write one of the $4000-$4013, $4015 and $4017 registers
+    pub fn write(&mut self, address: usize, value: u8) {
+        self.dmc.write(address, value);
+
+        if address == 0x4017 {
+            self.irq_inhibit = value & 0x40 != 0;
+            if self.irq_inhibit {
+                self.frame_irq = false;
+            }
+
+            // the sequence restarts 3 or 4 cycles later, depending on
+            // the CPU cycle parity
+            let delay = if self.cycle & 1 == 1 { 4 } else { 3 };
+            self.frame_write = Some((value, delay));
+        }
+    }
+}
@@ -1,21 +1,38 @@
+use crate::apu::Apu;
 use crate::cartridge::*;
 use crate::controller::*;
+use crate::cpu::types::IrqSource;
 use crate::cpu::*;
+use crate::dma::Dma;
 use crate::memory::*;
 use crate::ppu::*;
+use crate::region::Region;
 use std::sync::{Arc, Mutex};
 
+// An event at the dot the PPU is about to run
+fn ppu_event(ppu: &PPU, kind: PPUEventKind, address: u16, value: u8, pc: u16) -> PPUEvent {
+    PPUEvent {
+        kind,
+        scanline: ppu.scanline(),
+        cycle: ppu.cycle(),
+        address,
+        value,
+        pc,
+    }
+}
+
 pub struct NesMemoryMapper {
     ram: Vec<u8>,
     cartridge: CartridgeRef,
     ppu: PPURef,
     pub controllers: Vec<ControllerRef>,
+    apu: Apu,
+
+    // page written to $4014, picked up by the DMA unit
+    oam_dma_request: Option<u8>,
 
-    oam_dma_page: u8,
-    oam_dma_address: u8,
-    dma_data: u8,
-    pub do_oam_dma: bool,
-    pub oam_dma_cycle: i8,
+    // address of the CPU instruction doing the current access
+    instruction_pc: u16,
 }
 
 impl NesMemoryMapper {
@@ -29,38 +46,18 @@ impl NesMemoryMapper {
             ram: vec![0; 0x0800],
             ppu,
             controllers,
-            oam_dma_page: 0,
-            oam_dma_address: 0,
-            dma_data: 0,
-            oam_dma_cycle: 1,
-            do_oam_dma: false,
+            apu: Apu::new(),
+            oam_dma_request: None,
+            instruction_pc: 0,
         }
     }
 
-    pub fn transfer_oam(&mut self, cycle: u32) {
-        if self.oam_dma_cycle > 0 {
-            if cycle & 1 == 1 {
-                self.oam_dma_cycle += 1;
-            }
-
-            self.oam_dma_cycle -= 1;
-        } else {
-            if cycle & 1 == 1 {
-                let address = (self.oam_dma_page as usize) << 8 | (self.oam_dma_address as usize);
-                self.dma_data = self.read(address, false);
-            } else {
-                let mut ppu = self.ppu.lock().unwrap();
-                let address = ppu.oam_address;
-                ppu.write_oam_address(address as usize, self.dma_data);
-                self.oam_dma_address = self.oam_dma_address.wrapping_add(1);
-                ppu.oam_address = address.wrapping_add(1);
-
-                if self.oam_dma_address == 0 {
-                    self.oam_dma_cycle = 1;
-                    self.do_oam_dma = false;
-                }
-            }
-        }
+    /// Write one byte of OAM DMA at OAMADDR
+    pub fn write_oam(&mut self, value: u8) {
+        let mut ppu = self.ppu.lock().unwrap();
+        let address = ppu.oam_address;
+        ppu.write_oam_address(address as usize, value);
+        ppu.oam_address = address.wrapping_add(1);
     }
 }
 
@@ -79,8 +76,24 @@ impl Memory for NesMemoryMapper {
             self.ram[address & 0x07FF]
         } else if address < 0x4000 {
             let mut ppu = self.ppu.lock().unwrap();
-            ppu.read(address & 0x07, is_read_only)
-        } else if address <= 0x4013 || (address == 0x4015) || (address == 0x4017) {
+            let value = ppu.read(address & 0x07, is_read_only);
+
+            if !is_read_only {
+                let address = 0x2000 | (address & 0x07) as u16;
+                let event = ppu_event(
+                    &ppu,
+                    PPUEventKind::RegisterRead,
+                    address,
+                    value,
+                    self.instruction_pc,
+                );
+                ppu.record_event(event);
+            }
+
+            value
+        } else if address == 0x4015 {
+            self.apu.read_status(is_read_only)
+        } else if address <= 0x4013 || (address == 0x4017) {
             0
         } else if address == 0x4016 || address == 0x4017 {
             let mut controller = self.controllers[address & 1].lock().unwrap();
@@ -103,13 +116,30 @@ impl Memory for NesMemoryMapper {
             self.ram[address & 0x07FF] = value;
         } else if address < 0x4000 {
             let mut ppu = self.ppu.lock().unwrap();
+            let register = 0x2000 | (address & 0x07) as u16;
+            let event = ppu_event(
+                &ppu,
+                PPUEventKind::RegisterWrite,
+                register,
+                value,
+                self.instruction_pc,
+            );
+            ppu.record_event(event);
             ppu.write(address & 0x07, value)
         } else if address == OAMDMA {
-            self.oam_dma_page = value;
-            self.oam_dma_address = 0;
-            self.do_oam_dma = true;
+            let mut ppu = self.ppu.lock().unwrap();
+            let event = ppu_event(
+                &ppu,
+                PPUEventKind::OamDma,
+                OAMDMA as u16,
+                value,
+                self.instruction_pc,
+            );
+            ppu.record_event(event);
+
+            self.oam_dma_request = Some(value);
         } else if address <= 0x4013 || (address == 0x4015) || (address == 0x4017) {
-            // TODO: APU here
+            self.apu.write(address, value);
         } else if address == 0x4016 || address == 0x4017 {
             let mut controller = self.controllers[address & 1].lock().unwrap();
             controller.write(value);
@@ -122,26 +152,44 @@ impl Memory for NesMemoryMapper {
 pub struct Bus {
     memory_mapper: NesMemoryMapper,
     pub cpu: CPU,
-    pub cycle: u32,
-    pub total_cycles: u32,
+    dma: Dma,
     pub ppu: PPURef,
+    region: Region,
+    // master clock cycles since power on, every other clock is derived
+    // from it by the region dividers
+    master_clock: u64,
 }
 
 impl Bus {
     pub fn new(cartridge: Cartridge) -> Self {
+        let region = cartridge.region();
         let cartref = Arc::new(Mutex::new(cartridge));
         let ppu = Arc::new(Mutex::new(PPU::new(cartref.clone())));
         let controller1 = Controller::new_ref();
         let controller2 = Controller::new_ref();
         let controllers = vec![controller1, controller2];
 
-        Bus {
+        let mut bus = Bus {
             memory_mapper: NesMemoryMapper::new(ppu.clone(), cartref, controllers),
             cpu: CPU::new(),
-            cycle: 0,
-            total_cycles: 0,
+            dma: Dma::new(),
             ppu,
-        }
+            region,
+            master_clock: 0,
+        };
+
+        bus.set_region(region);
+        bus
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.lock().unwrap().set_region(region);
+        self.memory_mapper.apu.set_region(region);
     }
 
     pub fn new_from_array(array: &Vec<u8>) -> Result<Self, String> {
@@ -156,31 +204,121 @@ impl Bus {
     pub fn clock(&mut self) {
         {
             let mut ppu = self.ppu.lock().unwrap();
+            let (scanline, cycle) = (ppu.scanline(), ppu.cycle());
 
             ppu.clock();
 
             if ppu.call_nmi {
                 ppu.call_nmi = false;
+                ppu.record_event(PPUEvent {
+                    kind: PPUEventKind::Nmi,
+                    scanline,
+                    cycle,
+                    address: INTERRUPT_NMI,
+                    value: 0,
+                    pc: self.cpu.instruction_pc(),
+                });
                 self.cpu.nmi();
             }
         }
 
-        match self.cycle {
-            0 | 3 => {
-                if self.memory_mapper.do_oam_dma {
-                    self.memory_mapper.transfer_oam(self.total_cycles);
-                } else {
-                    self.cpu.clock(&mut self.memory_mapper);
+        // the CPU runs when the master clock passed one of its edges
+        // since the previous dot, starting with the very first dot
+        let cpu_divider = self.region.cpu_divider();
+        if self.master_clock % cpu_divider < self.region.ppu_divider() {
+            let cycle = self.master_clock / cpu_divider;
+            self.clock_apu(cycle);
+            self.update_mapper_irq();
+            self.clock_cpu(cycle);
+        }
+
+        self.master_clock += self.region.ppu_divider();
+    }
+
+    // Either the CPU or the DMA unit gets the bus on a CPU cycle. The DMA
+    // can only take it when the CPU is about to read.
+    fn clock_cpu(&mut self, cycle: u64) {
+        if let Some(page) = self.memory_mapper.oam_dma_request.take() {
+            self.dma.start_oam(page);
+        }
+
+        self.memory_mapper.instruction_pc = self.cpu.instruction_pc();
+
+        let is_halted = if self.dma.is_halted() {
+            self.dma.clock(&mut self.memory_mapper, cycle);
+            true
+        } else if self.dma.is_running() {
+            match self.cpu.next_read_address() {
+                Some(address) => {
+                    self.dma.halt(&mut self.memory_mapper, address);
+                    true
                 }
+                None => false,
             }
-            _ => {}
+        } else {
+            false
+        };
+
+        // the CPU is stalled, but its cycles still go by and the next one
+        // it runs picks up the count from the master clock
+        if !is_halted {
+            self.cpu.clock_at(&mut self.memory_mapper, cycle);
         }
-        if self.cycle == 0 {
-            self.cycle = 6;
+    }
+
+    /// Fetch a DMC sample byte, halting the CPU for 1 to 4 cycles
+    pub fn request_dmc_dma(&mut self, address: u16) {
+        self.dma.start_dmc(address);
+    }
+
+    /// The sample byte fetched by the last DMC DMA
+    pub fn take_dmc_sample(&mut self) -> Option<u8> {
+        self.dma.take_dmc_sample()
+    }
+
+    /// Master clock cycles since power on
+    pub fn master_clock(&self) -> u64 {
+        self.master_clock
+    }
+
+    fn clock_apu(&mut self, cycle: u64) {
+        let apu = &mut self.memory_mapper.apu;
+        apu.clock(cycle);
+
+        if apu.is_dmc_dma_pending() {
+            if let Some(sample) = self.dma.take_dmc_sample() {
+                apu.fill_dmc_sample(sample);
+            }
+        }
+        if let Some(address) = apu.take_dmc_dma_request() {
+            self.dma.start_dmc(address);
         }
 
-        self.cycle -= 1;
-        self.total_cycles += 1;
+        self.cpu.set_irq(IrqSource::APU_FRAME, apu.frame_irq());
+        self.cpu.set_irq(IrqSource::APU_DMC, apu.dmc_irq());
+    }
+
+    fn update_mapper_irq(&mut self) {
+        let asserted = self.memory_mapper.cartridge.lock().unwrap().irq_pending();
+
+        if asserted && !self.cpu.irq_sources().contains(IrqSource::MAPPER) {
+            let mut ppu = self.ppu.lock().unwrap();
+            let event = ppu_event(
+                &ppu,
+                PPUEventKind::MapperIrq,
+                INTERRUPT_IRQ,
+                0,
+                self.cpu.instruction_pc(),
+            );
+            ppu.record_event(event);
+        }
+
+        self.cpu.set_irq(IrqSource::MAPPER, asserted);
+    }
+
+    /// Devices currently holding the IRQ line
+    pub fn irq_sources(&self) -> IrqSource {
+        self.cpu.irq_sources()
     }
 
     pub fn clock_until_frame_done(&mut self) {
@@ -201,6 +339,7 @@ impl Bus {
     }
 
     pub fn reset(&mut self) {
+        self.memory_mapper.apu.reset();
         self.cpu.reset();
     }
 
@@ -220,11 +359,14 @@ impl Bus {
         controller.set_button_status(button, state);
     }
 
-    pub fn cpu_total_cycles(&self) -> u32 {
-        self.cpu.total_cycles
+    pub fn cpu_total_cycles(&self) -> u64 {
+        match self.master_clock.checked_sub(self.region.ppu_divider()) {
+            Some(last_dot) => last_dot / self.region.cpu_divider() + 1,
+            None => 0,
+        }
     }
 
-    pub fn ppu_total_cycles(&self) -> u32 {
-        self.total_cycles
+    pub fn ppu_total_cycles(&self) -> u64 {
+        self.master_clock / self.region.ppu_divider()
     }
 }
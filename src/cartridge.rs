@@ -1,5 +1,6 @@
 use crate::mappers::*;
 use crate::memory::Memory;
+use crate::region::Region;
 use modular_bitfield::prelude::*;
 use std::io::prelude::*;
 use std::io::Cursor;
@@ -137,10 +138,34 @@ impl Cartridge {
         self.header
     }
 
+    pub fn is_nes20(&self) -> bool {
+        self.header.mapper2 & 0x0c == 0x08
+    }
+
+    /// The TV system the ROM expects, from the NES 2.0 timing field
+    /// or the iNES TV system flag
+    pub fn region(&self) -> Region {
+        if self.is_nes20() {
+            match self.header.unused[1] & 0x03 {
+                1 => Region::Pal,
+                3 => Region::Dendy,
+                _ => Region::Ntsc,
+            }
+        } else if self.header.tv_system1 & 0x01 > 0 {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        }
+    }
+
     pub fn mirroring(&self) -> MirroringMode {
         self.hw_mirroring
     }
 
+    pub fn irq_pending(&self) -> bool {
+        self.mapper.irq_pending()
+    }
+
     pub fn use_cartridge_data(&self) -> bool {
         self.use_cartridge_data
     }
@@ -1,7 +1,9 @@
 mod clock;
 mod opcodes;
+pub mod trace;
 pub mod types;
 
+use crate::cpu::trace::*;
 use crate::cpu::types::*;
 
 /// This constant represents the address of the low byte of 6502's reset vector address
@@ -11,10 +13,33 @@ pub const INTERRUPT_IRQ: u16 = 0xFFFE;
 
 use crate::memory::Memory;
 
+// Records the first bus access of a cycle without side effects
+struct ProbeMemory {
+    access: Option<(usize, bool)>,
+}
+
+impl Memory for ProbeMemory {
+    fn read(&mut self, address: usize, is_read_only: bool) -> u8 {
+        if !is_read_only && self.access.is_none() {
+            self.access = Some((address, true));
+        }
+        0
+    }
+
+    fn write(&mut self, address: usize, _value: u8) {
+        if self.access.is_none() {
+            self.access = Some((address, false));
+        }
+    }
+}
+
 /// Emulating 6502 CPU
 pub struct CPU {
+    variant: CpuVariant,
     pub regs: CPURegisters,
-    pub total_cycles: u32,
+    /// Cycles run so far. On a bus it follows the master clock, including
+    /// the cycles the CPU spends halted.
+    pub total_cycles: u64,
     cycles: u32,
     opcode: u8,
     pub interrupt_type: Interrupt,
@@ -33,23 +58,38 @@ pub struct CPU {
     branch_when: bool,
     shift_op: Option<ShiftBinaryOperation>,
 
-    pub irq_pin: Pin,
-    pub nmi_pin: Pin,
-    stop_irq_pin: Pin,
+    irq_sources: IrqSource,
+
+    // interrupt lines as polled at the end of the current and the
+    // previous cycle
+    run_irq: bool,
+    prev_run_irq: bool,
+    need_nmi: bool,
+    prev_need_nmi: bool,
+    // the BRK sequence is running for an interrupt, not a BRK opcode
+    hardware_interrupt: bool,
+    vector: u16,
 
     // for debug
+    trace: Option<BusTrace>,
     instruction_debug: Vec<u8>,
     prev_pc: u16,
-    prev_cycles: u32,
+    prev_cycles: u64,
     formatted_params: String,
     formatted_register: String,
     pub debug: bool,
 }
 
 impl CPU {
-    /// Create new CPU instance
+    /// Create new CPU instance, a `CpuVariant::Ricoh2A03` core as found in the NES
     pub fn new() -> Self {
+        Self::new_with_variant(CpuVariant::default())
+    }
+
+    /// Create a CPU emulating another 6502 flavour than the NES one
+    pub fn new_with_variant(variant: CpuVariant) -> Self {
         CPU {
+            variant,
             regs: Default::default(),
             total_cycles: 0,
             cycles: 0,
@@ -66,9 +106,13 @@ impl CPU {
             address: Int16::new_from_16(0),
             tmp_address: Int16::new_from_16(0),
             register_access: RegisterAccess::None,
-            irq_pin: Pin::default(),
-            nmi_pin: Pin::default(),
-            stop_irq_pin: Pin::default(),
+            irq_sources: IrqSource::empty(),
+            run_irq: false,
+            prev_run_irq: false,
+            need_nmi: false,
+            prev_need_nmi: false,
+            hardware_interrupt: false,
+            vector: INTERRUPT_RESET,
             branch_status_to_test: StatusFlag::empty(),
             branch_when: false,
             shift_op: None,
@@ -77,6 +121,7 @@ impl CPU {
             prev_pc: 0,
             prev_cycles: 0,
             formatted_params: String::new(),
+            trace: None,
             formatted_register: String::new(),
             debug: false,
         }
@@ -88,16 +133,36 @@ impl CPU {
         self.next_state(Microcode::FetchOpcode);
     }
 
-    pub fn irq(&mut self) {
-        if !self.regs.p.contains(StatusFlag::I) || !self.stop_irq_pin.is_pulled() {
-            self.interrupt_type |= Interrupt::IRQ;
-        }
+    /// Assert or acknowledge the IRQ of one source. The IRQ keeps being
+    /// taken while any source asserts it and I is clear.
+    pub fn set_irq(&mut self, source: IrqSource, asserted: bool) {
+        self.irq_sources.set(source, asserted);
+    }
+
+    pub fn irq_sources(&self) -> IrqSource {
+        self.irq_sources
+    }
+
+    /// Put the CPU at the start of an instruction with the given
+    /// registers, without going through reset
+    pub fn set_registers(&mut self, regs: CPURegisters) {
+        self.regs = regs;
+        self.interrupt_type.clear();
+        self.run_irq = false;
+        self.prev_run_irq = false;
+        self.need_nmi = false;
+        self.prev_need_nmi = false;
+        self.next_state(Microcode::FetchOpcode);
     }
 
     pub fn nmi(&mut self) {
         self.interrupt_type |= Interrupt::NMI;
     }
 
+    pub fn variant(&self) -> CpuVariant {
+        self.variant
+    }
+
     pub fn done(&self) -> bool {
         match self.state {
             Microcode::FetchOpcode => true,
@@ -109,6 +174,80 @@ impl CPU {
         self.is_read
     }
 
+    /// The address the next cycle reads from, or None when it writes.
+    /// The cycle is run on a snapshot of the CPU, so nothing is touched.
+    /// A cycle that doesn't use the bus, as in WAI and STP, reads PC.
+    pub fn next_read_address(&self) -> Option<usize> {
+        let mut probe = ProbeMemory { access: None };
+        self.snapshot().run_next_state(&mut probe);
+
+        match probe.access {
+            Some((address, true)) => Some(address),
+            Some((_, false)) => None,
+            None => Some(self.regs.pc as usize),
+        }
+    }
+
+    // A copy of the execution state, without the trace and debug output
+    fn snapshot(&self) -> CPU {
+        CPU {
+            variant: self.variant,
+            regs: self.regs,
+            total_cycles: self.total_cycles,
+            cycles: self.cycles,
+            opcode: self.opcode,
+            interrupt_type: self.interrupt_type,
+            address_mode: self.address_mode,
+            opcode_type: self.opcode_type,
+            is_read: self.is_read,
+            temp: self.temp,
+            state: self.state,
+            absolute_address: self.absolute_address,
+            relative_address: self.relative_address,
+            fetched_data: self.fetched_data,
+            address: self.address,
+            tmp_address: self.tmp_address,
+            register_access: self.register_access,
+            irq_sources: self.irq_sources,
+            run_irq: self.run_irq,
+            prev_run_irq: self.prev_run_irq,
+            need_nmi: self.need_nmi,
+            prev_need_nmi: self.prev_need_nmi,
+            hardware_interrupt: self.hardware_interrupt,
+            vector: self.vector,
+            branch_status_to_test: self.branch_status_to_test,
+            branch_when: self.branch_when,
+            shift_op: self.shift_op,
+
+            instruction_debug: Vec::new(),
+            prev_pc: self.prev_pc,
+            prev_cycles: self.prev_cycles,
+            formatted_params: String::new(),
+            trace: None,
+            formatted_register: String::new(),
+            debug: false,
+        }
+    }
+
+    /// Address of the instruction being executed
+    pub fn instruction_pc(&self) -> u16 {
+        self.prev_pc
+    }
+
+    /// Start recording the last `capacity` bus cycles
+    pub fn enable_trace(&mut self, capacity: usize) {
+        self.trace = Some(BusTrace::new(capacity));
+    }
+
+    /// Stop recording and hand over what was recorded
+    pub fn disable_trace(&mut self) -> Option<BusTrace> {
+        self.trace.take()
+    }
+
+    pub fn trace(&self) -> Option<&BusTrace> {
+        self.trace.as_ref()
+    }
+
     fn see_prev_pc(&self) -> String {
         format!("{:04X}", self.prev_pc)
     }
@@ -176,15 +315,29 @@ impl CPU {
     fn read(&mut self, memory: &mut dyn Memory, address: usize) -> u8 {
         self.is_read = true;
         self.advance_cycle();
-        memory.read(address, false)
+        let value = memory.read(address, false);
+        self.trace_cycle(address, value);
+        value
     }
 
     fn write(&mut self, memory: &mut dyn Memory, address: usize, value: u8) {
         self.is_read = false;
         self.advance_cycle();
+        self.trace_cycle(address, value);
         memory.write(address, value);
     }
 
+    fn trace_cycle(&mut self, address: usize, value: u8) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.record(BusCycle {
+                cycle: self.total_cycles,
+                address: address as u16,
+                value,
+                is_read: self.is_read,
+            });
+        }
+    }
+
     fn push_stack(&mut self, memory: &mut dyn Memory, value: u8) {
         let address = 0x0100 + self.regs.sp as usize;
         self.write(memory, address, value);
@@ -197,9 +350,35 @@ impl CPU {
         self.read(memory, address)
     }
 
+    // Read-modify-write instructions write the unmodified value back,
+    // the 65C02 reads it again instead
+    fn rmw_dummy_access(&mut self, memory: &mut dyn Memory) {
+        if self.variant.is_cmos() {
+            self.read(memory, self.absolute_address);
+        } else {
+            self.write(memory, self.absolute_address, self.fetched_data);
+        }
+    }
+
+    // a stack access that only reads, with no change to SP
+    fn read_stack(&mut self, memory: &mut dyn Memory) {
+        let address = 0x0100 + self.regs.sp as usize;
+        self.read(memory, address);
+    }
+
+    // RESET goes through the pushes with writes turned into reads
+    fn push_interrupt_stack(&mut self, memory: &mut dyn Memory, value: u8) {
+        if self.interrupt_type.contains(Interrupt::RESET) {
+            self.read_stack(memory);
+            self.regs.sp = self.regs.sp.wrapping_sub(1);
+        } else {
+            self.push_stack(memory, value);
+        }
+    }
+
     fn set_instruction(&mut self) {
         let opcode_num = self.opcode as usize;
-        match &OPCODE_TABLE[opcode_num] {
+        match &self.variant.opcode_table()[opcode_num] {
             (address_mode, opcode, cycle) => {
                 self.address_mode = *address_mode;
                 self.opcode_type = *opcode;
@@ -231,27 +410,24 @@ impl CPU {
 
     fn is_write_instruction(&self) -> bool {
         match self.opcode_type {
-            Opcode::Asl
-            | Opcode::Lsr
-            | Opcode::Rol
-            | Opcode::Ror
-            | Opcode::Dec
-            | Opcode::Inc
-            | Opcode::Sta
-            | Opcode::Stx
-            | Opcode::Sty => true,
+            Opcode::Dec | Opcode::Inc | Opcode::Sta | Opcode::Stx | Opcode::Sty | Opcode::Stz => {
+                true
+            }
+            // the 65C02 skips the extra cycle of shifts without a page cross
+            Opcode::Asl | Opcode::Lsr | Opcode::Rol | Opcode::Ror => !self.variant.is_cmos(),
             _ => false,
         }
     }
 
-    fn vector_address(&self) -> usize {
-        if self.interrupt_type.contains(Interrupt::RESET) {
-            INTERRUPT_RESET as usize
-        } else if self.interrupt_type.contains(Interrupt::NMI) {
-            INTERRUPT_NMI as usize
-        } else {
-            INTERRUPT_IRQ as usize
-        }
+    // Interrupts are polled at the end of every cycle. An instruction
+    // only gets interrupted by what was seen at the end of its
+    // second-to-last cycle.
+    fn poll_interrupts(&mut self) {
+        self.prev_need_nmi = self.need_nmi;
+        self.need_nmi = self.interrupt_type.contains(Interrupt::NMI);
+
+        self.prev_run_irq = self.run_irq;
+        self.run_irq = !self.irq_sources.is_empty() && !self.regs.p.contains(StatusFlag::I);
     }
 
     fn set_nz(&mut self, value: u8) {
@@ -10,9 +10,18 @@ impl CPU {
 
     // Clock the CPU
     pub fn clock(&mut self, memory: &mut dyn Memory) {
+        self.clock_at(memory, self.total_cycles);
+    }
+
+    /// Run cycle number `cycle`, as counted by whatever drives the CPU.
+    /// The trace, the debug output and `total_cycles` follow that count.
+    pub fn clock_at(&mut self, memory: &mut dyn Memory, cycle: u64) {
+        self.total_cycles = cycle;
+
         self.run_next_state(memory);
+        self.poll_interrupts();
 
-        self.total_cycles += 1;
+        self.total_cycles = cycle + 1;
     }
 
     pub fn run_next_state(&mut self, memory: &mut dyn Memory) {
@@ -21,15 +30,13 @@ impl CPU {
                 self.instruction_debug.clear();
                 self.prev_pc = self.regs.pc;
 
-                // println!("{:08b}", self.interrupt_type.bits());
+                self.hardware_interrupt = self.interrupt_type.contains(Interrupt::RESET)
+                    || self.prev_need_nmi
+                    || self.prev_run_irq;
 
-                if self.interrupt_type.contains(Interrupt::NMI)
-                    || self.interrupt_type.contains(Interrupt::RESET)
-                {
-                    self.opcode = 0;
-                } else if self.interrupt_type.contains(Interrupt::IRQ)
-                    && !self.regs.p.contains(StatusFlag::I)
-                {
+                if self.hardware_interrupt {
+                    // the opcode is read but thrown away, PC isn't moved
+                    self.read(memory, self.regs.pc as usize);
                     self.opcode = 0;
                 } else {
                     self.opcode = self.get_next_pc_value(memory);
@@ -58,7 +65,13 @@ impl CPU {
                     }
                     AddressMode::Imp => {
                         self.register_access = RegisterAccess::None;
-                        self.next_state(Microcode::Execute);
+
+                        // the 65C02 has single cycle NOPs
+                        if self.cycles == 1 {
+                            self.fetch_opcode();
+                        } else {
+                            self.next_state(Microcode::Execute);
+                        }
                     }
                     AddressMode::Imm => {
                         self.register_access = RegisterAccess::None;
@@ -91,12 +104,16 @@ impl CPU {
                     AddressMode::Izx => {
                         self.next_state(Microcode::FetchIZX1);
                     }
-                    AddressMode::Izy => {
+                    AddressMode::Izy | AddressMode::Izp => {
                         self.next_state(Microcode::FetchIZY1);
                     }
-                    AddressMode::Ind => {
+                    AddressMode::Ind | AddressMode::Iax => {
                         self.next_state(Microcode::IndReadLo);
                     }
+                    AddressMode::Zpr => {
+                        self.register_access = RegisterAccess::None;
+                        self.next_state(Microcode::FetchLoZP);
+                    }
                     AddressMode::Rel => {
                         match self.opcode_type {
                             Opcode::Bpl => {
@@ -131,6 +148,10 @@ impl CPU {
                                 self.branch_status_to_test = StatusFlag::Z;
                                 self.branch_when = true;
                             }
+                            Opcode::Bra => {
+                                self.branch_status_to_test = StatusFlag::empty();
+                                self.branch_when = true;
+                            }
                             _ => {
                                 // impossible
                             }
@@ -163,7 +184,11 @@ impl CPU {
                         self.next_state(Microcode::FetchHiY);
                     }
                     _ => {
-                        self.next_state(Microcode::FetchHi);
+                        if let Opcode::Jsr = self.opcode_type {
+                            self.next_state(Microcode::JsrReadStack);
+                        } else {
+                            self.next_state(Microcode::FetchHi);
+                        }
                     }
                 }
             }
@@ -177,6 +202,7 @@ impl CPU {
                         if self.debug {
                             write!(self.formatted_params, "${:02X},X", self.address.lo).unwrap();
                         }
+                        self.temp = self.address.lo;
                         self.address.lo = self.address.lo.wrapping_add(self.regs.x);
                         self.next_state(Microcode::FetchLoZP1);
                     }
@@ -184,6 +210,7 @@ impl CPU {
                         if self.debug {
                             write!(self.formatted_params, "${:02X},Y", self.address.lo).unwrap();
                         }
+                        self.temp = self.address.lo;
                         self.address.lo = self.address.lo.wrapping_add(self.regs.y);
                         self.next_state(Microcode::FetchLoZP1);
                     }
@@ -210,6 +237,8 @@ impl CPU {
                 }
             }
             Microcode::FetchLoZP1 => {
+                // the unindexed address is read while the index is added
+                self.read(memory, self.temp as usize);
                 self.absolute_address = self.address.to_usize();
                 self.next_state(Microcode::Execute);
             }
@@ -221,7 +250,7 @@ impl CPU {
                     self.instruction_debug.push(self.address.hi);
 
                     match self.opcode_type {
-                        Opcode::Jmp | Opcode::Jsr => {
+                        Opcode::Jmp => {
                             write!(self.formatted_params, "${:04X}", self.absolute_address)
                                 .unwrap();
                         }
@@ -241,8 +270,6 @@ impl CPU {
                     // JMP ABS use 3 cycles -_-
                     self.regs.pc = self.absolute_address as u16;
                     self.fetch_opcode();
-                } else if let Opcode::Jsr = self.opcode_type {
-                    self.next_state(Microcode::JsrSaveHiPrevPc);
                 } else {
                     self.next_state(Microcode::Execute);
                 }
@@ -337,7 +364,11 @@ impl CPU {
             Microcode::FetchIZY1 => {
                 self.temp = self.get_next_pc_value(memory);
                 if self.debug {
-                    write!(self.formatted_params, "(${:02X}),Y", self.temp).unwrap();
+                    if let AddressMode::Izp = self.address_mode {
+                        write!(self.formatted_params, "(${:02X})", self.temp).unwrap();
+                    } else {
+                        write!(self.formatted_params, "(${:02X}),Y", self.temp).unwrap();
+                    }
                     self.instruction_debug.push(self.temp);
                 }
                 self.next_state(Microcode::FetchIZY2);
@@ -363,16 +394,24 @@ impl CPU {
                     .unwrap();
                 }
 
-                self.address += self.regs.y;
-
-                if self.address.has_carry() || self.is_write_instruction() {
-                    self.next_state(Microcode::SetCrossPage);
-                } else {
+                // (zp) is (zp),Y without the index
+                if let AddressMode::Izp = self.address_mode {
                     self.absolute_address = self.address.to_usize();
                     self.next_state(Microcode::Execute);
+                } else {
+                    self.address += self.regs.y;
+
+                    if self.address.has_carry() || self.is_write_instruction() {
+                        self.next_state(Microcode::SetCrossPage);
+                    } else {
+                        self.absolute_address = self.address.to_usize();
+                        self.next_state(Microcode::Execute);
+                    }
                 }
             }
             Microcode::SetCrossPage => {
+                // read from the address before the high byte is fixed
+                self.read(memory, self.address.to_usize());
                 self.address.add_hi_from_carry();
                 self.absolute_address = self.address.to_usize();
                 self.next_state(Microcode::Execute);
@@ -388,13 +427,36 @@ impl CPU {
                 self.tmp_address.hi = self.get_next_pc_value(memory);
                 if self.debug {
                     self.instruction_debug.push(self.tmp_address.hi);
-                    write!(
-                        self.formatted_params,
-                        "(${:04X})",
-                        self.tmp_address.to_u16()
-                    )
-                    .unwrap();
+                    if let AddressMode::Iax = self.address_mode {
+                        write!(
+                            self.formatted_params,
+                            "(${:04X},X)",
+                            self.tmp_address.to_u16()
+                        )
+                        .unwrap();
+                    } else {
+                        write!(
+                            self.formatted_params,
+                            "(${:04X})",
+                            self.tmp_address.to_u16()
+                        )
+                        .unwrap();
+                    }
                 }
+
+                if let AddressMode::Iax = self.address_mode {
+                    self.tmp_address += self.regs.x;
+                    self.tmp_address.add_hi_from_carry();
+                }
+
+                if self.variant.is_cmos() {
+                    self.next_state(Microcode::IndDummyRead);
+                } else {
+                    self.next_state(Microcode::IndReadActualLo);
+                }
+            }
+            Microcode::IndDummyRead => {
+                self.read(memory, self.regs.pc.wrapping_sub(1) as usize);
                 self.next_state(Microcode::IndReadActualLo);
             }
             Microcode::IndReadActualLo => {
@@ -405,7 +467,11 @@ impl CPU {
                 // JMP indirect has a bug:
                 // if address is $xxff, the actual jump fetched
                 // is in $xxff and $xx00 (instead of $xxff + 1)
+                // The 65C02 fixed it.
                 self.tmp_address += 1;
+                if self.variant.is_cmos() {
+                    self.tmp_address.add_hi_from_carry();
+                }
                 self.address.hi = self.read(memory, self.tmp_address.to_usize());
                 write!(self.formatted_params, " = {:04X}", self.address.to_u16()).unwrap();
 
@@ -414,11 +480,18 @@ impl CPU {
                 self.fetch_opcode();
             }
             Microcode::Execute => {
+                // implied instructions read the next byte anyway
+                if let AddressMode::Imp = self.address_mode {
+                    self.read(memory, self.regs.pc as usize);
+                }
+
                 self.do_instruction(memory);
             }
 
             // ASL
             Microcode::ShiftA => {
+                self.read(memory, self.regs.pc as usize);
+
                 let func = self.shift_op.unwrap();
                 let mut fetched = self.regs.a as u16;
                 let result = func(fetched, (self.regs.p.bits() & 0x01) as u16);
@@ -438,7 +511,7 @@ impl CPU {
                 self.next_state(Microcode::ShiftWrite);
             }
             Microcode::ShiftWrite => {
-                self.write(memory, self.absolute_address, self.fetched_data);
+                self.rmw_dummy_access(memory);
                 self.next_state(Microcode::ShiftAddAndWrite);
             }
             Microcode::ShiftAddAndWrite => {
@@ -458,74 +531,91 @@ impl CPU {
 
             // BRK
             Microcode::BrkPushPCHi => {
-                // Skip next PC if it is invoked from BRK instruction
-                // (not interupted by any means)
-                if self.interrupt_type.bits() == 0 {
-                    self.get_pc();
-                }
-
                 self.address.set_u16(self.regs.pc);
-
-                if self.interrupt_type.contains(Interrupt::RESET) {
-                    self.push_stack(memory, 0);
-                } else {
-                    self.push_stack(memory, self.address.hi);
-                }
+                self.push_interrupt_stack(memory, self.address.hi);
 
                 self.next_state(Microcode::BrkPushPCLo);
             }
             Microcode::BrkPushPCLo => {
-                if self.interrupt_type.contains(Interrupt::RESET) {
-                    self.push_stack(memory, 0);
-                } else {
-                    self.push_stack(memory, self.address.lo);
-                }
+                self.push_interrupt_stack(memory, self.address.lo);
 
                 self.next_state(Microcode::BrkPushStatus);
             }
             Microcode::BrkPushStatus => {
-                self.regs.p |= StatusFlag::B;
+                // An NMI seen before the status is pushed hijacks the
+                // vector of both BRK and IRQ
+                self.vector = if self.interrupt_type.contains(Interrupt::RESET) {
+                    INTERRUPT_RESET
+                } else if self.need_nmi {
+                    self.interrupt_type.remove(Interrupt::NMI);
+                    self.need_nmi = false;
+                    INTERRUPT_NMI
+                } else {
+                    INTERRUPT_IRQ
+                };
+
+                // only BRK pushes the B flag
+                if !self.hardware_interrupt {
+                    self.regs.p |= StatusFlag::B;
+                }
                 self.regs.p |= StatusFlag::U;
+                self.push_interrupt_stack(memory, self.regs.p.bits());
 
-                if self.interrupt_type.contains(Interrupt::RESET) {
-                    self.push_stack(memory, 0);
-                } else {
-                    self.push_stack(memory, self.regs.p.bits());
+                if !self.interrupt_type.contains(Interrupt::RESET) {
                     self.regs.p &= !StatusFlag::U;
                 }
 
                 self.regs.p &= !StatusFlag::B;
                 self.regs.p |= StatusFlag::I;
 
+                if self.variant.is_cmos() {
+                    self.regs.p &= !StatusFlag::D;
+                }
+
                 self.next_state(Microcode::BrkPushReadPCLo);
             }
             Microcode::BrkPushReadPCLo => {
-                self.address.lo = self.read(memory, self.vector_address());
+                self.address.lo = self.read(memory, self.vector as usize);
 
                 self.next_state(Microcode::BrkPushReadPCHi);
             }
             Microcode::BrkPushReadPCHi => {
-                self.address.hi = self.read(memory, self.vector_address() + 1);
+                self.address.hi = self.read(memory, self.vector as usize + 1);
 
                 self.next_state(Microcode::BrkSetPC);
+                self.run_next_state(memory);
             }
             Microcode::BrkSetPC => {
-                self.interrupt_type.clear();
+                self.interrupt_type.remove(Interrupt::RESET);
                 self.regs.pc = self.address.to_u16();
+
+                // the first instruction of the handler always runs
+                self.need_nmi = false;
+                self.run_irq = false;
                 self.fetch_opcode();
             }
             // PHA
             Microcode::PhaPushStack => {
-                self.push_stack(memory, self.regs.a);
+                let value = match self.opcode_type {
+                    Opcode::Phx => self.regs.x,
+                    Opcode::Phy => self.regs.y,
+                    _ => self.regs.a,
+                };
+                self.push_stack(memory, value);
                 self.fetch_opcode();
             }
             // PLA
             Microcode::PlaPull => {
-                self.fetched_data = self.pop_stack(memory);
+                self.read_stack(memory);
                 self.next_state(Microcode::PlaPull1);
             }
             Microcode::PlaPull1 => {
-                self.regs.a = self.fetched_data;
+                self.fetched_data = self.pop_stack(memory);
+                match self.opcode_type {
+                    Opcode::Plx => self.regs.x = self.fetched_data,
+                    Opcode::Ply => self.regs.y = self.fetched_data,
+                    _ => self.regs.a = self.fetched_data,
+                }
                 self.set_nz(self.fetched_data);
                 self.fetch_opcode();
             }
@@ -536,18 +626,24 @@ impl CPU {
             }
             // PLP
             Microcode::PlpPull => {
-                self.fetched_data = self.pop_stack(memory);
+                self.read_stack(memory);
                 self.next_state(Microcode::PlpPull1);
             }
             Microcode::PlpPull1 => {
+                self.fetched_data = self.pop_stack(memory);
                 self.regs.p.set_from_byte(self.fetched_data);
                 self.regs.p.set(StatusFlag::B, false);
                 self.regs.p.set(StatusFlag::U, true);
                 self.fetch_opcode();
             }
             // JSR
+            Microcode::JsrReadStack => {
+                self.read_stack(memory);
+                self.next_state(Microcode::JsrSaveHiPrevPc);
+            }
             Microcode::JsrSaveHiPrevPc => {
-                self.tmp_address.set_u16(self.regs.pc - 1);
+                // PC still points to the high byte of the address
+                self.tmp_address.set_u16(self.regs.pc);
                 self.push_stack(memory, self.tmp_address.hi);
                 self.next_state(Microcode::JsrSaveLoPrevPc);
             }
@@ -556,6 +652,14 @@ impl CPU {
                 self.next_state(Microcode::JsrJump);
             }
             Microcode::JsrJump => {
+                self.address.hi = self.get_next_pc_value(memory);
+                self.absolute_address = self.address.to_usize();
+
+                if self.debug {
+                    self.instruction_debug.push(self.address.hi);
+                    write!(self.formatted_params, "${:04X}", self.absolute_address).unwrap();
+                }
+
                 self.regs.pc = self.absolute_address as u16;
                 self.fetch_opcode();
             }
@@ -566,16 +670,24 @@ impl CPU {
             }
             Microcode::RtsGetPcHi => {
                 self.tmp_address.hi = self.pop_stack(memory);
-                self.next_state(Microcode::RtsWasteOneCycle);
+                self.next_state(Microcode::RtsJump);
             }
             Microcode::RtsWasteOneCycle => {
-                self.next_state(Microcode::RtsJump);
+                self.read_stack(memory);
+                self.next_state(Microcode::RtsGetPcLo);
             }
             Microcode::RtsJump => {
-                self.regs.pc = self.tmp_address.to_u16() + 1;
+                // the pulled address is read once more while PC is increased
+                self.regs.pc = self.tmp_address.to_u16();
+                self.read(memory, self.regs.pc as usize);
+                self.regs.pc = self.regs.pc.wrapping_add(1);
                 self.fetch_opcode();
             }
             // RTI
+            Microcode::RtiReadStack => {
+                self.read_stack(memory);
+                self.next_state(Microcode::RtiPopStatus);
+            }
             Microcode::RtiPopStatus => {
                 let status = self.pop_stack(memory);
                 self.regs.p.set_from_byte(status);
@@ -590,6 +702,7 @@ impl CPU {
                 let hi = self.pop_stack(memory);
                 self.address.hi = hi;
                 self.next_state(Microcode::RtiSetPC);
+                self.run_next_state(memory);
             }
             Microcode::RtiSetPC => {
                 self.regs.pc = self.address.to_u16();
@@ -604,16 +717,34 @@ impl CPU {
                     self.instruction_debug.push(next_pc);
 
                     let next_pc = (self.regs.pc as i32) + (self.relative_address as i32);
+                    if let AddressMode::Zpr = self.address_mode {
+                        write!(self.formatted_params, ", ").unwrap();
+                    }
                     write!(self.formatted_params, "${:04X}", next_pc).unwrap();
                 }
 
-                if self.regs.p.contains(self.branch_status_to_test) == self.branch_when {
+                let bit = 1 << ((self.opcode >> 4) & 0x07);
+                let is_taken = match self.opcode_type {
+                    Opcode::Bbr => self.fetched_data & bit == 0,
+                    Opcode::Bbs => self.fetched_data & bit != 0,
+                    _ => self.regs.p.contains(self.branch_status_to_test) == self.branch_when,
+                };
+
+                if is_taken {
                     self.next_state(Microcode::BranchJumpIfTrue);
                 } else {
                     self.fetch_opcode();
                 }
             }
             Microcode::BranchJumpIfTrue => {
+                // a taken branch doesn't poll IRQ on this cycle, so an IRQ
+                // that just showed up waits for one more instruction
+                if self.run_irq && !self.prev_run_irq {
+                    self.run_irq = false;
+                }
+
+                self.read(memory, self.regs.pc as usize);
+
                 let next_pc = (self.regs.pc as i32) + (self.relative_address as i32);
                 if (self.regs.pc & 0xff00) == (next_pc as u16) & 0xff00 {
                     self.regs.pc = next_pc as u16;
@@ -624,6 +755,11 @@ impl CPU {
             }
             Microcode::BranchJumpIfTrueAndCrossPage => {
                 let next_pc = (self.regs.pc as i32) + (self.relative_address as i32);
+
+                // PC is read with only the low byte moved
+                let address = (self.regs.pc & 0xff00) | (next_pc as u16 & 0x00ff);
+                self.read(memory, address as usize);
+
                 self.regs.pc = next_pc as u16;
                 self.fetch_opcode();
             }
@@ -641,14 +777,72 @@ impl CPU {
             }
             // DEC & INC
             Microcode::IncDecWriteOld => {
-                self.write(memory, self.absolute_address, self.fetched_data);
+                self.rmw_dummy_access(memory);
                 self.next_state(Microcode::IncDecWriteNew);
             }
             Microcode::IncDecWriteNew => {
                 self.write(memory, self.absolute_address, self.temp);
-                self.set_nz(self.temp);
+                if let Opcode::Inc | Opcode::Dec = self.opcode_type {
+                    self.set_nz(self.temp);
+                }
                 self.fetch_opcode();
             }
+            Microcode::IncDecA => {
+                self.read(memory, self.regs.pc as usize);
+                self.regs.a = match self.opcode_type {
+                    Opcode::Inc => self.regs.a.wrapping_add(1),
+                    _ => self.regs.a.wrapping_sub(1),
+                };
+                self.set_nz(self.regs.a);
+                self.fetch_opcode();
+            }
+            // TRB, TSB, RMB, SMB
+            Microcode::BitsReadData => {
+                self.fetched_data = self.read(memory, self.absolute_address);
+                let bit = 1 << ((self.opcode >> 4) & 0x07);
+
+                self.temp = match self.opcode_type {
+                    Opcode::Trb => self.fetched_data & !self.regs.a,
+                    Opcode::Tsb => self.fetched_data | self.regs.a,
+                    Opcode::Rmb => self.fetched_data & !bit,
+                    _ => self.fetched_data | bit,
+                };
+
+                if let Opcode::Trb | Opcode::Tsb = self.opcode_type {
+                    self.regs
+                        .p
+                        .set(StatusFlag::Z, self.fetched_data & self.regs.a == 0);
+                }
+
+                self.next_state(Microcode::IncDecWriteOld);
+            }
+            // BBR, BBS
+            Microcode::ZprDummyRead => {
+                self.read(memory, self.absolute_address);
+                self.next_state(Microcode::BranchReadOffsetAndCheck);
+            }
+            Microcode::DecimalDummyRead => {
+                self.read(memory, self.absolute_address);
+                self.fetch_opcode();
+            }
+            Microcode::LongNop => {
+                self.read(memory, 0xffff);
+
+                if self.cycles == 4 {
+                    self.fetch_opcode();
+                }
+            }
+            // WAI
+            Microcode::Wait => {
+                if self.interrupt_type.contains(Interrupt::RESET)
+                    || self.need_nmi
+                    || !self.irq_sources.is_empty()
+                {
+                    self.fetch_opcode();
+                }
+            }
+            // STP, only a reset gets out of it
+            Microcode::Stop => {}
             _ => {
                 if self.cycles > 0 {
                     self.cycles -= 1;
@@ -21,15 +21,81 @@ fn ror(value: u16, carry: u16) -> (u16, bool) {
     (result, (value & 0x01) > 0)
 }
 
+// BCD addition as the NMOS 6502 does it. Returns the result, carry,
+// and the N and V flags, which come from before the high nibble is
+// adjusted.
+fn adc_decimal(a: u8, value: u8, carry: u8) -> (u8, bool, bool, bool) {
+    let mut lo = (a & 0x0f) as i16 + (value & 0x0f) as i16 + carry as i16;
+    if lo >= 0x0a {
+        lo = ((lo + 0x06) & 0x0f) + 0x10;
+    }
+
+    let signed = (a & 0xf0) as i8 as i16 + (value & 0xf0) as i8 as i16 + lo;
+    let negative = signed & 0x80 > 0;
+    let overflow = !(-128..=127).contains(&signed);
+
+    let mut result = (a & 0xf0) as i16 + (value & 0xf0) as i16 + lo;
+    if result >= 0xa0 {
+        result += 0x60;
+    }
+
+    ((result & 0xff) as u8, result >= 0x100, negative, overflow)
+}
+
+fn sbc_decimal(a: u8, value: u8, carry: u8) -> u8 {
+    let mut lo = (a & 0x0f) as i16 - (value & 0x0f) as i16 + carry as i16 - 1;
+    if lo < 0 {
+        lo = ((lo - 0x06) & 0x0f) - 0x10;
+    }
+
+    let mut result = (a & 0xf0) as i16 - (value & 0xf0) as i16 + lo;
+    if result < 0 {
+        result -= 0x60;
+    }
+
+    (result & 0xff) as u8
+}
+
+fn sbc_decimal_cmos(a: u8, value: u8, carry: u8) -> u8 {
+    let lo = (a & 0x0f) as i16 - (value & 0x0f) as i16 + carry as i16 - 1;
+
+    let mut result = a as i16 - value as i16 + carry as i16 - 1;
+    if result < 0 {
+        result -= 0x60;
+    }
+    if lo < 0 {
+        result -= 0x06;
+    }
+
+    (result & 0xff) as u8
+}
+
 impl CPU {
+    fn is_decimal_mode(&self) -> bool {
+        self.variant.has_decimal_mode() && self.regs.p.contains(StatusFlag::D)
+    }
+
+    // decimal ADC/SBC take one more cycle on the 65C02
+    fn finish_arithmetic(&mut self) {
+        if self.variant.is_cmos() && self.is_decimal_mode() {
+            self.next_state(Microcode::DecimalDummyRead);
+        } else {
+            self.fetch_opcode();
+        }
+    }
+
     pub fn do_instruction(&mut self, memory: &mut dyn Memory) {
         match self.opcode_type {
             Opcode::Brk => {
+                // BRK skips the byte after it, interrupts don't
+                if !self.hardware_interrupt {
+                    self.get_pc();
+                }
+
                 self.next_state(Microcode::BrkPushPCHi);
-                self.run_next_state(memory);
             }
             Opcode::Rti => {
-                self.next_state(Microcode::RtiPopStatus);
+                self.next_state(Microcode::RtiReadStack);
             }
             Opcode::Lda => {
                 self.regs.a = self.read(memory, self.absolute_address);
@@ -58,6 +124,10 @@ impl CPU {
                 self.write(memory, self.absolute_address, self.regs.y);
                 self.fetch_opcode();
             }
+            Opcode::Stz => {
+                self.write(memory, self.absolute_address, 0);
+                self.fetch_opcode();
+            }
             Opcode::Asl | Opcode::Lsr | Opcode::Rol | Opcode::Ror => {
                 self.shift_op = match self.opcode_type {
                     Opcode::Asl => Some(asl),
@@ -127,10 +197,10 @@ impl CPU {
                 self.set_nz(self.regs.x);
                 self.fetch_opcode();
             }
-            Opcode::Pha => {
+            Opcode::Pha | Opcode::Phx | Opcode::Phy => {
                 self.next_state(Microcode::PhaPushStack);
             }
-            Opcode::Pla => {
+            Opcode::Pla | Opcode::Plx | Opcode::Ply => {
                 self.next_state(Microcode::PlaPull);
             }
             Opcode::Php => {
@@ -148,7 +218,7 @@ impl CPU {
                 self.fetch_opcode();
             }
             Opcode::Rts => {
-                self.next_state(Microcode::RtsGetPcLo);
+                self.next_state(Microcode::RtsWasteOneCycle);
             }
             Opcode::Sei => {
                 self.regs.p |= StatusFlag::I;
@@ -201,6 +271,12 @@ impl CPU {
                     self.regs.p &= !StatusFlag::Z;
                 }
 
+                // BIT #imm only touches Z
+                if let AddressMode::Imm = self.address_mode {
+                    self.fetch_opcode();
+                    return;
+                }
+
                 let bit = (self.regs.p.bits() & 0b0011_1111) | (self.fetched_data & 0b1100_0000);
                 self.regs.p.set_from_byte(bit);
                 self.fetch_opcode();
@@ -218,7 +294,24 @@ impl CPU {
 
                 self.regs.a = (result & 0xff) as u8;
                 self.set_nz(self.regs.a);
-                self.fetch_opcode();
+
+                if self.is_decimal_mode() {
+                    let (result, carry, negative, overflow) =
+                        adc_decimal(a as u8, fetched_data as u8, carry as u8);
+                    self.regs.a = result;
+                    self.regs.p.set(StatusFlag::C, carry);
+                    self.regs.p.set(StatusFlag::V, overflow);
+
+                    // NMOS leaves Z from the binary sum and N from before
+                    // the high nibble is adjusted
+                    if self.variant.is_cmos() {
+                        self.set_nz(result);
+                    } else {
+                        self.regs.p.set(StatusFlag::N, negative);
+                    }
+                }
+
+                self.finish_arithmetic();
             }
             Opcode::Sbc => {
                 let fetched_data = self.read(memory, self.absolute_address);
@@ -235,7 +328,21 @@ impl CPU {
                     (!(a ^ temp) & (a ^ (result & 0xff)) & 0x0080) > 0,
                 );
                 self.set_nz(self.regs.a);
-                self.fetch_opcode();
+
+                // flags stay binary, except N and Z on the 65C02
+                if self.is_decimal_mode() {
+                    self.regs.a = if self.variant.is_cmos() {
+                        sbc_decimal_cmos(a as u8, fetched_data, carry as u8)
+                    } else {
+                        sbc_decimal(a as u8, fetched_data, carry as u8)
+                    };
+
+                    if self.variant.is_cmos() {
+                        self.set_nz(self.regs.a);
+                    }
+                }
+
+                self.finish_arithmetic();
             }
             Opcode::Cmp => {
                 let fetched_data = self.read(memory, self.absolute_address);
@@ -261,6 +368,9 @@ impl CPU {
                 self.regs.p.set(StatusFlag::N, result < 0x00);
                 self.fetch_opcode();
             }
+            Opcode::Dec | Opcode::Inc if matches!(self.register_access, RegisterAccess::A) => {
+                self.next_state(Microcode::IncDecA);
+            }
             Opcode::Dec => {
                 self.next_state(Microcode::DecReadData);
                 self.run_next_state(memory);
@@ -269,6 +379,38 @@ impl CPU {
                 self.next_state(Microcode::IncReadData);
                 self.run_next_state(memory);
             }
+            Opcode::Trb | Opcode::Tsb | Opcode::Rmb | Opcode::Smb => {
+                self.next_state(Microcode::BitsReadData);
+                self.run_next_state(memory);
+            }
+            Opcode::Bbr | Opcode::Bbs => {
+                self.fetched_data = self.read(memory, self.absolute_address);
+                self.next_state(Microcode::ZprDummyRead);
+            }
+            Opcode::Wai => {
+                self.next_state(Microcode::Wait);
+            }
+            Opcode::Stp => {
+                self.next_state(Microcode::Stop);
+            }
+            Opcode::Nop | Opcode::Xxx => {
+                // NOPs with an operand still read it
+                match self.address_mode {
+                    AddressMode::Imp => {}
+                    // except $5C on the 65C02, which reads $FFxx instead
+                    // and then goes on reading $FFFF
+                    AddressMode::Abs if self.opcode == 0x5c && self.variant.is_cmos() => {
+                        self.read(memory, 0xff00 | (self.absolute_address & 0xff));
+                        self.next_state(Microcode::LongNop);
+                        return;
+                    }
+                    _ => {
+                        self.read(memory, self.absolute_address);
+                    }
+                }
+
+                self.fetch_opcode();
+            }
             _ => {
                 if self.cycles > 0 {
                     self.cycles -= 1;
@@ -0,0 +1,79 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+/// One CPU bus access
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BusCycle {
+    pub cycle: u64,
+    pub address: u16,
+    pub value: u8,
+    pub is_read: bool,
+}
+
+impl fmt::Display for BusCycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {:04X} {:02X} {}",
+            self.cycle,
+            self.address,
+            self.value,
+            if self.is_read { "R" } else { "W" }
+        )
+    }
+}
+
+/// Keeps the last `capacity` bus cycles. Formatting it gives one
+/// `cycle address value R/W` line per cycle.
+#[derive(Debug, Clone)]
+pub struct BusTrace {
+    cycles: VecDeque<BusCycle>,
+    capacity: usize,
+}
+
+impl BusTrace {
+    pub fn new(capacity: usize) -> Self {
+        BusTrace {
+            cycles: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn record(&mut self, cycle: BusCycle) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.cycles.len() == self.capacity {
+            self.cycles.pop_front();
+        }
+
+        self.cycles.push_back(cycle);
+    }
+
+    pub fn cycles(&self) -> impl Iterator<Item = &BusCycle> {
+        self.cycles.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cycles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cycles.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.cycles.clear();
+    }
+}
+
+impl fmt::Display for BusTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for cycle in self.cycles.iter() {
+            writeln!(f, "{}", cycle)?;
+        }
+
+        Ok(())
+    }
+}
@@ -27,6 +27,7 @@ impl fmt::Display for StatusFlag {
 }
 
 /// Representing 6502's registers
+#[derive(Clone, Copy)]
 pub struct CPURegisters {
     /// Representing A register (accumulator)
     pub a: u8,
@@ -55,59 +56,61 @@ impl Default for CPURegisters {
     }
 }
 
-bitflags! {
-    pub struct Interrupt: u8 {
-        const IRQ = 1 << 0;
-        const NMI = 1 << 1;
-        const RESET = 1 << 2;
-    }
+/// The 6502 flavour being emulated
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CpuVariant {
+    /// The NES CPU: an NMOS 6502 with decimal mode cut off
+    #[default]
+    Ricoh2A03,
+    /// NMOS 6502 with BCD arithmetic
+    Nmos6502,
+    /// WDC 65C02
+    Wdc65C02,
 }
 
-impl Interrupt {
-    pub fn clear(&mut self) {
-        self.bits = 0;
+impl CpuVariant {
+    pub fn has_decimal_mode(&self) -> bool {
+        *self != CpuVariant::Ricoh2A03
     }
-}
 
-pub struct Pin {
-    pub delay: u32,
-    pub state: bool,
-}
+    pub fn is_cmos(&self) -> bool {
+        *self == CpuVariant::Wdc65C02
+    }
 
-impl Pin {
-    pub fn default() -> Pin {
-        Pin {
-            delay: 0,
-            state: false,
+    pub fn opcode_table(&self) -> &'static [(AddressMode, Opcode, u32); 256] {
+        if self.is_cmos() {
+            &CMOS_OPCODE_TABLE
+        } else {
+            &OPCODE_TABLE
         }
     }
+}
 
-    pub fn pull(&mut self) {
-        self.delay = 0;
-        self.state = true;
+bitflags! {
+    pub struct Interrupt: u8 {
+        const NMI = 1 << 1;
+        const RESET = 1 << 2;
     }
+}
 
+impl Interrupt {
     pub fn clear(&mut self) {
-        self.delay = 0;
-        self.state = false;
-    }
-
-    pub fn pull_with_delay(&mut self, delay: u32) {
-        self.delay = delay;
-        self.state = true;
-    }
-
-    pub fn is_pulled(&self) -> bool {
-        self.delay == 0 && self.state
+        self.bits = 0;
     }
+}
 
-    pub fn decrease_delay(&mut self) {
-        if self.delay > 0 {
-            self.delay -= 1;
-        }
+bitflags! {
+    /// Devices sharing the /IRQ line. The line stays asserted as long
+    /// as any of them holds it.
+    pub struct IrqSource: u8 {
+        const APU_FRAME = 1 << 0;
+        const APU_DMC = 1 << 1;
+        const MAPPER = 1 << 2;
+        const EXTERNAL = 1 << 3;
     }
 }
 
+#[derive(Clone, Copy)]
 pub struct Int16 {
     pub lo: u8,
     pub hi: u8,
@@ -185,6 +188,7 @@ impl AddAssign<u8> for Int16 {
     }
 }
 
+#[derive(Clone, Copy)]
 pub enum RegisterAccess {
     A,
     X,
@@ -194,6 +198,7 @@ pub enum RegisterAccess {
 
 pub type ShiftBinaryOperation = fn(u16, u16) -> (u16, bool);
 
+#[derive(Clone, Copy)]
 pub enum Microcode {
     FetchOpcode,
     FetchParameters,
@@ -236,6 +241,7 @@ pub enum Microcode {
     // JMP IND
     IndReadLo,
     IndReadHi,
+    IndDummyRead,
     IndReadActualLo,
     IndReadActualHiAndJump,
 
@@ -271,6 +277,7 @@ pub enum Microcode {
     PlpPull1,
 
     // JSR
+    JsrReadStack,
     JsrSaveHiPrevPc,
     JsrSaveLoPrevPc,
     JsrJump,
@@ -282,6 +289,7 @@ pub enum Microcode {
     RtsWasteOneCycle,
 
     // RTI
+    RtiReadStack,
     RtiPopStatus,
     RtiPopLoPC,
     RtiPopHiPC,
@@ -298,9 +306,28 @@ pub enum Microcode {
 
     IncDecWriteOld,
     IncDecWriteNew,
+
+    // INC A, DEC A
+    IncDecA,
+
+    // TRB, TSB, RMB, SMB
+    BitsReadData,
+
+    // BBR, BBS
+    ZprDummyRead,
+
+    // extra cycle of decimal ADC/SBC on the 65C02
+    DecimalDummyRead,
+
+    // the 8 cycle NOP $5C of the 65C02
+    LongNop,
+
+    // WAI, STP
+    Wait,
+    Stop,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AddressMode {
     Imp,
     Acc,
@@ -315,9 +342,13 @@ pub enum AddressMode {
     Izy,
     Rel,
     Ind,
+    // 65C02: (zp), (abs,X) and zp,rel
+    Izp,
+    Iax,
+    Zpr,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Opcode {
     // Xxx is dummy opcode
     Xxx,
@@ -377,6 +408,22 @@ pub enum Opcode {
     Tsx,
     Dex,
     Nop,
+
+    // 65C02
+    Bra,
+    Phx,
+    Phy,
+    Plx,
+    Ply,
+    Stz,
+    Trb,
+    Tsb,
+    Rmb,
+    Smb,
+    Bbr,
+    Bbs,
+    Wai,
+    Stp,
 }
 
 impl fmt::Display for AddressMode {
@@ -649,3 +696,263 @@ pub const OPCODE_TABLE: [(AddressMode, Opcode, u32); 256] = [
     /* fe */ (AddressMode::Abx, Opcode::Inc, 7),
     /* ff */ (AddressMode::Imp, Opcode::Xxx, 7),
 ];
+
+/// Opcodes of the WDC 65C02
+pub const CMOS_OPCODE_TABLE: [(AddressMode, Opcode, u32); 256] = [
+    /* 00 */ (AddressMode::Imp, Opcode::Brk, 7),
+    /* 01 */ (AddressMode::Izx, Opcode::Ora, 6),
+    /* 02 */ (AddressMode::Imm, Opcode::Nop, 2),
+    /* 03 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 04 */ (AddressMode::Zp0, Opcode::Tsb, 5),
+    /* 05 */ (AddressMode::Zp0, Opcode::Ora, 3),
+    /* 06 */ (AddressMode::Zp0, Opcode::Asl, 5),
+    /* 07 */ (AddressMode::Zp0, Opcode::Rmb, 5),
+    /* 08 */ (AddressMode::Imp, Opcode::Php, 3),
+    /* 09 */ (AddressMode::Imm, Opcode::Ora, 2),
+    /* 0a */ (AddressMode::Acc, Opcode::Asl, 2),
+    /* 0b */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 0c */ (AddressMode::Abs, Opcode::Tsb, 6),
+    /* 0d */ (AddressMode::Abs, Opcode::Ora, 4),
+    /* 0e */ (AddressMode::Abs, Opcode::Asl, 6),
+    /* 0f */ (AddressMode::Zpr, Opcode::Bbr, 5),
+    /* 10 */ (AddressMode::Rel, Opcode::Bpl, 2),
+    /* 11 */ (AddressMode::Izy, Opcode::Ora, 5),
+    /* 12 */ (AddressMode::Izp, Opcode::Ora, 5),
+    /* 13 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 14 */ (AddressMode::Zp0, Opcode::Trb, 5),
+    /* 15 */ (AddressMode::Zpx, Opcode::Ora, 4),
+    /* 16 */ (AddressMode::Zpx, Opcode::Asl, 6),
+    /* 17 */ (AddressMode::Zp0, Opcode::Rmb, 5),
+    /* 18 */ (AddressMode::Imp, Opcode::Clc, 2),
+    /* 19 */ (AddressMode::Aby, Opcode::Ora, 4),
+    /* 1a */ (AddressMode::Acc, Opcode::Inc, 2),
+    /* 1b */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 1c */ (AddressMode::Abs, Opcode::Trb, 6),
+    /* 1d */ (AddressMode::Abx, Opcode::Ora, 4),
+    /* 1e */ (AddressMode::Abx, Opcode::Asl, 6),
+    /* 1f */ (AddressMode::Zpr, Opcode::Bbr, 5),
+    /* 20 */ (AddressMode::Abs, Opcode::Jsr, 6),
+    /* 21 */ (AddressMode::Izx, Opcode::And, 6),
+    /* 22 */ (AddressMode::Imm, Opcode::Nop, 2),
+    /* 23 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 24 */ (AddressMode::Zp0, Opcode::Bit, 3),
+    /* 25 */ (AddressMode::Zp0, Opcode::And, 3),
+    /* 26 */ (AddressMode::Zp0, Opcode::Rol, 5),
+    /* 27 */ (AddressMode::Zp0, Opcode::Rmb, 5),
+    /* 28 */ (AddressMode::Imp, Opcode::Plp, 4),
+    /* 29 */ (AddressMode::Imm, Opcode::And, 2),
+    /* 2a */ (AddressMode::Acc, Opcode::Rol, 2),
+    /* 2b */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 2c */ (AddressMode::Abs, Opcode::Bit, 4),
+    /* 2d */ (AddressMode::Abs, Opcode::And, 4),
+    /* 2e */ (AddressMode::Abs, Opcode::Rol, 6),
+    /* 2f */ (AddressMode::Zpr, Opcode::Bbr, 5),
+    /* 30 */ (AddressMode::Rel, Opcode::Bmi, 2),
+    /* 31 */ (AddressMode::Izy, Opcode::And, 5),
+    /* 32 */ (AddressMode::Izp, Opcode::And, 5),
+    /* 33 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 34 */ (AddressMode::Zpx, Opcode::Bit, 4),
+    /* 35 */ (AddressMode::Zpx, Opcode::And, 4),
+    /* 36 */ (AddressMode::Zpx, Opcode::Rol, 6),
+    /* 37 */ (AddressMode::Zp0, Opcode::Rmb, 5),
+    /* 38 */ (AddressMode::Imp, Opcode::Sec, 2),
+    /* 39 */ (AddressMode::Aby, Opcode::And, 4),
+    /* 3a */ (AddressMode::Acc, Opcode::Dec, 2),
+    /* 3b */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 3c */ (AddressMode::Abx, Opcode::Bit, 4),
+    /* 3d */ (AddressMode::Abx, Opcode::And, 4),
+    /* 3e */ (AddressMode::Abx, Opcode::Rol, 6),
+    /* 3f */ (AddressMode::Zpr, Opcode::Bbr, 5),
+    /* 40 */ (AddressMode::Imp, Opcode::Rti, 6),
+    /* 41 */ (AddressMode::Izx, Opcode::Eor, 6),
+    /* 42 */ (AddressMode::Imm, Opcode::Nop, 2),
+    /* 43 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 44 */ (AddressMode::Zp0, Opcode::Nop, 3),
+    /* 45 */ (AddressMode::Zp0, Opcode::Eor, 3),
+    /* 46 */ (AddressMode::Zp0, Opcode::Lsr, 5),
+    /* 47 */ (AddressMode::Zp0, Opcode::Rmb, 5),
+    /* 48 */ (AddressMode::Imp, Opcode::Pha, 3),
+    /* 49 */ (AddressMode::Imm, Opcode::Eor, 2),
+    /* 4a */ (AddressMode::Acc, Opcode::Lsr, 2),
+    /* 4b */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 4c */ (AddressMode::Abs, Opcode::Jmp, 3),
+    /* 4d */ (AddressMode::Abs, Opcode::Eor, 4),
+    /* 4e */ (AddressMode::Abs, Opcode::Lsr, 6),
+    /* 4f */ (AddressMode::Zpr, Opcode::Bbr, 5),
+    /* 50 */ (AddressMode::Rel, Opcode::Bvc, 2),
+    /* 51 */ (AddressMode::Izy, Opcode::Eor, 5),
+    /* 52 */ (AddressMode::Izp, Opcode::Eor, 5),
+    /* 53 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 54 */ (AddressMode::Zpx, Opcode::Nop, 4),
+    /* 55 */ (AddressMode::Zpx, Opcode::Eor, 4),
+    /* 56 */ (AddressMode::Zpx, Opcode::Lsr, 6),
+    /* 57 */ (AddressMode::Zp0, Opcode::Rmb, 5),
+    /* 58 */ (AddressMode::Imp, Opcode::Cli, 2),
+    /* 59 */ (AddressMode::Aby, Opcode::Eor, 4),
+    /* 5a */ (AddressMode::Imp, Opcode::Phy, 3),
+    /* 5b */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 5c */ (AddressMode::Abs, Opcode::Nop, 8),
+    /* 5d */ (AddressMode::Abx, Opcode::Eor, 4),
+    /* 5e */ (AddressMode::Abx, Opcode::Lsr, 6),
+    /* 5f */ (AddressMode::Zpr, Opcode::Bbr, 5),
+    /* 60 */ (AddressMode::Imp, Opcode::Rts, 6),
+    /* 61 */ (AddressMode::Izx, Opcode::Adc, 6),
+    /* 62 */ (AddressMode::Imm, Opcode::Nop, 2),
+    /* 63 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 64 */ (AddressMode::Zp0, Opcode::Stz, 3),
+    /* 65 */ (AddressMode::Zp0, Opcode::Adc, 3),
+    /* 66 */ (AddressMode::Zp0, Opcode::Ror, 5),
+    /* 67 */ (AddressMode::Zp0, Opcode::Rmb, 5),
+    /* 68 */ (AddressMode::Imp, Opcode::Pla, 4),
+    /* 69 */ (AddressMode::Imm, Opcode::Adc, 2),
+    /* 6a */ (AddressMode::Acc, Opcode::Ror, 2),
+    /* 6b */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 6c */ (AddressMode::Ind, Opcode::Jmp, 6),
+    /* 6d */ (AddressMode::Abs, Opcode::Adc, 4),
+    /* 6e */ (AddressMode::Abs, Opcode::Ror, 6),
+    /* 6f */ (AddressMode::Zpr, Opcode::Bbr, 5),
+    /* 70 */ (AddressMode::Rel, Opcode::Bvs, 2),
+    /* 71 */ (AddressMode::Izy, Opcode::Adc, 5),
+    /* 72 */ (AddressMode::Izp, Opcode::Adc, 5),
+    /* 73 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 74 */ (AddressMode::Zpx, Opcode::Stz, 4),
+    /* 75 */ (AddressMode::Zpx, Opcode::Adc, 4),
+    /* 76 */ (AddressMode::Zpx, Opcode::Ror, 6),
+    /* 77 */ (AddressMode::Zp0, Opcode::Rmb, 5),
+    /* 78 */ (AddressMode::Imp, Opcode::Sei, 2),
+    /* 79 */ (AddressMode::Aby, Opcode::Adc, 4),
+    /* 7a */ (AddressMode::Imp, Opcode::Ply, 4),
+    /* 7b */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 7c */ (AddressMode::Iax, Opcode::Jmp, 6),
+    /* 7d */ (AddressMode::Abx, Opcode::Adc, 4),
+    /* 7e */ (AddressMode::Abx, Opcode::Ror, 6),
+    /* 7f */ (AddressMode::Zpr, Opcode::Bbr, 5),
+    /* 80 */ (AddressMode::Rel, Opcode::Bra, 3),
+    /* 81 */ (AddressMode::Izx, Opcode::Sta, 6),
+    /* 82 */ (AddressMode::Imm, Opcode::Nop, 2),
+    /* 83 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 84 */ (AddressMode::Zp0, Opcode::Sty, 3),
+    /* 85 */ (AddressMode::Zp0, Opcode::Sta, 3),
+    /* 86 */ (AddressMode::Zp0, Opcode::Stx, 3),
+    /* 87 */ (AddressMode::Zp0, Opcode::Smb, 5),
+    /* 88 */ (AddressMode::Imp, Opcode::Dey, 2),
+    /* 89 */ (AddressMode::Imm, Opcode::Bit, 2),
+    /* 8a */ (AddressMode::Imp, Opcode::Txa, 2),
+    /* 8b */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 8c */ (AddressMode::Abs, Opcode::Sty, 4),
+    /* 8d */ (AddressMode::Abs, Opcode::Sta, 4),
+    /* 8e */ (AddressMode::Abs, Opcode::Stx, 4),
+    /* 8f */ (AddressMode::Zpr, Opcode::Bbs, 5),
+    /* 90 */ (AddressMode::Rel, Opcode::Bcc, 2),
+    /* 91 */ (AddressMode::Izy, Opcode::Sta, 6),
+    /* 92 */ (AddressMode::Izp, Opcode::Sta, 5),
+    /* 93 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 94 */ (AddressMode::Zpx, Opcode::Sty, 4),
+    /* 95 */ (AddressMode::Zpx, Opcode::Sta, 4),
+    /* 96 */ (AddressMode::Zpy, Opcode::Stx, 4),
+    /* 97 */ (AddressMode::Zp0, Opcode::Smb, 5),
+    /* 98 */ (AddressMode::Imp, Opcode::Tya, 2),
+    /* 99 */ (AddressMode::Aby, Opcode::Sta, 5),
+    /* 9a */ (AddressMode::Imp, Opcode::Txs, 2),
+    /* 9b */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* 9c */ (AddressMode::Abs, Opcode::Stz, 4),
+    /* 9d */ (AddressMode::Abx, Opcode::Sta, 5),
+    /* 9e */ (AddressMode::Abx, Opcode::Stz, 5),
+    /* 9f */ (AddressMode::Zpr, Opcode::Bbs, 5),
+    /* a0 */ (AddressMode::Imm, Opcode::Ldy, 2),
+    /* a1 */ (AddressMode::Izx, Opcode::Lda, 6),
+    /* a2 */ (AddressMode::Imm, Opcode::Ldx, 2),
+    /* a3 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* a4 */ (AddressMode::Zp0, Opcode::Ldy, 3),
+    /* a5 */ (AddressMode::Zp0, Opcode::Lda, 3),
+    /* a6 */ (AddressMode::Zp0, Opcode::Ldx, 3),
+    /* a7 */ (AddressMode::Zp0, Opcode::Smb, 5),
+    /* a8 */ (AddressMode::Imp, Opcode::Tay, 2),
+    /* a9 */ (AddressMode::Imm, Opcode::Lda, 2),
+    /* aa */ (AddressMode::Imp, Opcode::Tax, 2),
+    /* ab */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* ac */ (AddressMode::Abs, Opcode::Ldy, 4),
+    /* ad */ (AddressMode::Abs, Opcode::Lda, 4),
+    /* ae */ (AddressMode::Abs, Opcode::Ldx, 4),
+    /* af */ (AddressMode::Zpr, Opcode::Bbs, 5),
+    /* b0 */ (AddressMode::Rel, Opcode::Bcs, 2),
+    /* b1 */ (AddressMode::Izy, Opcode::Lda, 5),
+    /* b2 */ (AddressMode::Izp, Opcode::Lda, 5),
+    /* b3 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* b4 */ (AddressMode::Zpx, Opcode::Ldy, 4),
+    /* b5 */ (AddressMode::Zpx, Opcode::Lda, 4),
+    /* b6 */ (AddressMode::Zpy, Opcode::Ldx, 4),
+    /* b7 */ (AddressMode::Zp0, Opcode::Smb, 5),
+    /* b8 */ (AddressMode::Imp, Opcode::Clv, 2),
+    /* b9 */ (AddressMode::Aby, Opcode::Lda, 4),
+    /* ba */ (AddressMode::Imp, Opcode::Tsx, 2),
+    /* bb */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* bc */ (AddressMode::Abx, Opcode::Ldy, 4),
+    /* bd */ (AddressMode::Abx, Opcode::Lda, 4),
+    /* be */ (AddressMode::Aby, Opcode::Ldx, 4),
+    /* bf */ (AddressMode::Zpr, Opcode::Bbs, 5),
+    /* c0 */ (AddressMode::Imm, Opcode::Cpy, 2),
+    /* c1 */ (AddressMode::Izx, Opcode::Cmp, 6),
+    /* c2 */ (AddressMode::Imm, Opcode::Nop, 2),
+    /* c3 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* c4 */ (AddressMode::Zp0, Opcode::Cpy, 3),
+    /* c5 */ (AddressMode::Zp0, Opcode::Cmp, 3),
+    /* c6 */ (AddressMode::Zp0, Opcode::Dec, 5),
+    /* c7 */ (AddressMode::Zp0, Opcode::Smb, 5),
+    /* c8 */ (AddressMode::Imp, Opcode::Iny, 2),
+    /* c9 */ (AddressMode::Imm, Opcode::Cmp, 2),
+    /* ca */ (AddressMode::Imp, Opcode::Dex, 2),
+    /* cb */ (AddressMode::Imp, Opcode::Wai, 3),
+    /* cc */ (AddressMode::Abs, Opcode::Cpy, 4),
+    /* cd */ (AddressMode::Abs, Opcode::Cmp, 4),
+    /* ce */ (AddressMode::Abs, Opcode::Dec, 6),
+    /* cf */ (AddressMode::Zpr, Opcode::Bbs, 5),
+    /* d0 */ (AddressMode::Rel, Opcode::Bne, 2),
+    /* d1 */ (AddressMode::Izy, Opcode::Cmp, 5),
+    /* d2 */ (AddressMode::Izp, Opcode::Cmp, 5),
+    /* d3 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* d4 */ (AddressMode::Zpx, Opcode::Nop, 4),
+    /* d5 */ (AddressMode::Zpx, Opcode::Cmp, 4),
+    /* d6 */ (AddressMode::Zpx, Opcode::Dec, 6),
+    /* d7 */ (AddressMode::Zp0, Opcode::Smb, 5),
+    /* d8 */ (AddressMode::Imp, Opcode::Cld, 2),
+    /* d9 */ (AddressMode::Aby, Opcode::Cmp, 4),
+    /* da */ (AddressMode::Imp, Opcode::Phx, 3),
+    /* db */ (AddressMode::Imp, Opcode::Stp, 3),
+    /* dc */ (AddressMode::Abs, Opcode::Nop, 4),
+    /* dd */ (AddressMode::Abx, Opcode::Cmp, 4),
+    /* de */ (AddressMode::Abx, Opcode::Dec, 7),
+    /* df */ (AddressMode::Zpr, Opcode::Bbs, 5),
+    /* e0 */ (AddressMode::Imm, Opcode::Cpx, 2),
+    /* e1 */ (AddressMode::Izx, Opcode::Sbc, 6),
+    /* e2 */ (AddressMode::Imm, Opcode::Nop, 2),
+    /* e3 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* e4 */ (AddressMode::Zp0, Opcode::Cpx, 3),
+    /* e5 */ (AddressMode::Zp0, Opcode::Sbc, 3),
+    /* e6 */ (AddressMode::Zp0, Opcode::Inc, 5),
+    /* e7 */ (AddressMode::Zp0, Opcode::Smb, 5),
+    /* e8 */ (AddressMode::Imp, Opcode::Inx, 2),
+    /* e9 */ (AddressMode::Imm, Opcode::Sbc, 2),
+    /* ea */ (AddressMode::Imp, Opcode::Nop, 2),
+    /* eb */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* ec */ (AddressMode::Abs, Opcode::Cpx, 4),
+    /* ed */ (AddressMode::Abs, Opcode::Sbc, 4),
+    /* ee */ (AddressMode::Abs, Opcode::Inc, 6),
+    /* ef */ (AddressMode::Zpr, Opcode::Bbs, 5),
+    /* f0 */ (AddressMode::Rel, Opcode::Beq, 2),
+    /* f1 */ (AddressMode::Izy, Opcode::Sbc, 5),
+    /* f2 */ (AddressMode::Izp, Opcode::Sbc, 5),
+    /* f3 */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* f4 */ (AddressMode::Zpx, Opcode::Nop, 4),
+    /* f5 */ (AddressMode::Zpx, Opcode::Sbc, 4),
+    /* f6 */ (AddressMode::Zpx, Opcode::Inc, 6),
+    /* f7 */ (AddressMode::Zp0, Opcode::Smb, 5),
+    /* f8 */ (AddressMode::Imp, Opcode::Sed, 2),
+    /* f9 */ (AddressMode::Aby, Opcode::Sbc, 4),
+    /* fa */ (AddressMode::Imp, Opcode::Plx, 4),
+    /* fb */ (AddressMode::Imp, Opcode::Nop, 1),
+    /* fc */ (AddressMode::Abs, Opcode::Nop, 4),
+    /* fd */ (AddressMode::Abx, Opcode::Sbc, 4),
+    /* fe */ (AddressMode::Abx, Opcode::Inc, 7),
+    /* ff */ (AddressMode::Zpr, Opcode::Bbs, 5),
+];
@@ -0,0 +1,279 @@
+pub mod symbols;
+
+use crate::cpu::types::*;
+use crate::disasm::symbols::Labels;
+use crate::memory::Memory;
+use std::fmt;
+use std::fmt::Write;
+
+/// Code/data log flag of a PRG byte that ran as code
+pub const CDL_CODE: u8 = 1 << 0;
+/// Code/data log flag of a PRG byte that was read as data
+pub const CDL_DATA: u8 = 1 << 1;
+
+/// One decoded instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub opcode: Opcode,
+    pub mode: AddressMode,
+    /// The value or address in the instruction. Branches keep the raw
+    /// offset here, BBR/BBS their zero page address.
+    pub operand: Option<u16>,
+    /// Where a branch, JMP or JSR goes, when it's known
+    pub target: Option<u16>,
+}
+
+impl Instruction {
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The mnemonic, with the bit number of the 65C02 bit instructions
+    pub fn mnemonic(&self) -> String {
+        let name = self.opcode.to_string().to_uppercase();
+
+        match self.opcode {
+            Opcode::Rmb | Opcode::Smb | Opcode::Bbr | Opcode::Bbs => {
+                format!("{}{}", name, (self.bytes[0] >> 4) & 0x07)
+            }
+            _ => name,
+        }
+    }
+
+    /// Format the instruction, naming the addresses that have a label
+    pub fn format(&self, labels: &Labels) -> String {
+        let operand = self.operand.unwrap_or(0);
+        let target = self.target.unwrap_or(0);
+        let name = |address: u16, width: usize| match labels.get(address) {
+            Some(label) => label.to_string(),
+            None => format!("${:0width$X}", address, width = width),
+        };
+
+        let params = match self.mode {
+            AddressMode::Imp => String::new(),
+            AddressMode::Acc => "A".to_string(),
+            AddressMode::Imm => format!("#${:02X}", operand),
+            AddressMode::Zp0 => name(operand, 2),
+            AddressMode::Zpx => format!("{},X", name(operand, 2)),
+            AddressMode::Zpy => format!("{},Y", name(operand, 2)),
+            AddressMode::Abs => name(operand, 4),
+            AddressMode::Abx => format!("{},X", name(operand, 4)),
+            AddressMode::Aby => format!("{},Y", name(operand, 4)),
+            AddressMode::Izx => format!("({},X)", name(operand, 2)),
+            AddressMode::Izy => format!("({}),Y", name(operand, 2)),
+            AddressMode::Izp => format!("({})", name(operand, 2)),
+            AddressMode::Ind => format!("({})", name(operand, 4)),
+            AddressMode::Iax => format!("({},X)", name(operand, 4)),
+            AddressMode::Rel => name(target, 4),
+            AddressMode::Zpr => format!("{},{}", name(operand, 2), name(target, 4)),
+        };
+
+        if params.is_empty() {
+            self.mnemonic()
+        } else {
+            format!("{} {}", self.mnemonic(), params)
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(&Labels::new()))
+    }
+}
+
+// number of bytes after the opcode
+fn operand_size(mode: AddressMode) -> usize {
+    match mode {
+        AddressMode::Imp | AddressMode::Acc => 0,
+        AddressMode::Abs
+        | AddressMode::Abx
+        | AddressMode::Aby
+        | AddressMode::Ind
+        | AddressMode::Iax
+        | AddressMode::Zpr => 2,
+        _ => 1,
+    }
+}
+
+/// Decodes instructions from memory or a byte slice
+pub struct Disassembler {
+    table: &'static [(AddressMode, Opcode, u32); 256],
+    pub labels: Labels,
+}
+
+impl Default for Disassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Disassembler {
+    pub fn new() -> Self {
+        Self::new_with_variant(CpuVariant::default())
+    }
+
+    pub fn new_with_variant(variant: CpuVariant) -> Self {
+        Disassembler {
+            table: variant.opcode_table(),
+            labels: Labels::new(),
+        }
+    }
+
+    /// Decode the instruction at `address`. Memory is read without side
+    /// effects.
+    pub fn decode(&self, memory: &mut dyn Memory, address: u16) -> Instruction {
+        self.decode_with(address, |address| memory.read(address as usize, true))
+    }
+
+    /// Decode the instruction at `address` of `bytes`, which start at
+    /// `base`. Bytes past the end read as 0.
+    pub fn decode_bytes(&self, bytes: &[u8], base: u16, address: u16) -> Instruction {
+        self.decode_with(address, |address| {
+            let offset = address.wrapping_sub(base) as usize;
+            bytes.get(offset).copied().unwrap_or(0)
+        })
+    }
+
+    /// Decode `count` instructions one after the other
+    pub fn disassemble(
+        &self,
+        memory: &mut dyn Memory,
+        address: u16,
+        count: usize,
+    ) -> Vec<Instruction> {
+        let mut instructions = Vec::with_capacity(count);
+        let mut address = address;
+
+        for _ in 0..count {
+            let instruction = self.decode(memory, address);
+            address = address.wrapping_add(instruction.len() as u16);
+            instructions.push(instruction);
+        }
+
+        instructions
+    }
+
+    pub fn format(&self, instruction: &Instruction) -> String {
+        instruction.format(&self.labels)
+    }
+
+    /// A listing of a PRG bank mapped at `base`. With a code/data log,
+    /// one flag byte per bank byte, only what ran as code is decoded and
+    /// the rest is listed as data. Without one, everything is decoded.
+    pub fn listing(&self, bank: &[u8], base: u16, cdl: Option<&[u8]>) -> String {
+        let is_code = |offset: usize| match cdl {
+            Some(cdl) => cdl.get(offset).is_some_and(|flags| flags & CDL_CODE != 0),
+            None => true,
+        };
+
+        let mut output = String::new();
+        let mut offset = 0;
+
+        while offset < bank.len() {
+            let address = base.wrapping_add(offset as u16);
+            if let Some(label) = self.labels.get(address) {
+                writeln!(output, "{}:", label).unwrap();
+            }
+
+            let mut is_cut_off = false;
+            if is_code(offset) {
+                let instruction = self.decode_bytes(bank, base, address);
+
+                // an instruction cut off by the end of the bank is data
+                if offset + instruction.len() <= bank.len() {
+                    let bytes = instruction
+                        .bytes
+                        .iter()
+                        .map(|byte| format!("{:02X}", byte))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    writeln!(
+                        output,
+                        "  {:04X}  {:8}  {}",
+                        address,
+                        bytes,
+                        self.format(&instruction)
+                    )
+                    .unwrap();
+
+                    offset += instruction.len();
+                    continue;
+                }
+
+                is_cut_off = true;
+            }
+
+            // data runs up to 8 bytes, and stop at code or a label
+            let mut values = vec![];
+            loop {
+                values.push(format!("${:02X}", bank[offset]));
+                offset += 1;
+
+                let next = base.wrapping_add(offset as u16);
+                if values.len() == 8
+                    || offset >= bank.len()
+                    || (is_code(offset) && !is_cut_off)
+                    || self.labels.get(next).is_some()
+                {
+                    break;
+                }
+            }
+
+            writeln!(output, "  {:04X}  .byte {}", address, values.join(", ")).unwrap();
+        }
+
+        output
+    }
+
+    fn decode_with(&self, address: u16, mut read: impl FnMut(u16) -> u8) -> Instruction {
+        let code = read(address);
+        let (mode, opcode, _) = self.table[code as usize];
+
+        let mut bytes = vec![code];
+        for offset in 1..=operand_size(mode) {
+            bytes.push(read(address.wrapping_add(offset as u16)));
+        }
+
+        let next = address.wrapping_add(bytes.len() as u16);
+        let (operand, target) = match mode {
+            AddressMode::Imp | AddressMode::Acc => (None, None),
+            AddressMode::Rel => {
+                let target = next.wrapping_add(bytes[1] as i8 as u16);
+                (Some(bytes[1] as u16), Some(target))
+            }
+            AddressMode::Zpr => {
+                let target = next.wrapping_add(bytes[2] as i8 as u16);
+                (Some(bytes[1] as u16), Some(target))
+            }
+            AddressMode::Abs
+            | AddressMode::Abx
+            | AddressMode::Aby
+            | AddressMode::Ind
+            | AddressMode::Iax => {
+                let word = (bytes[2] as u16) << 8 | bytes[1] as u16;
+                let target = match (opcode, mode) {
+                    (Opcode::Jmp, AddressMode::Abs) | (Opcode::Jsr, AddressMode::Abs) => Some(word),
+                    _ => None,
+                };
+                (Some(word), target)
+            }
+            _ => (Some(bytes[1] as u16), None),
+        };
+
+        Instruction {
+            address,
+            bytes,
+            opcode,
+            mode,
+            operand,
+            target,
+        }
+    }
+}
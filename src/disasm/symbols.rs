@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+/// Names of CPU addresses, loaded from the symbols of an assembler or
+/// the label files of other emulators
+#[derive(Debug, Clone, Default)]
+pub struct Labels {
+    names: HashMap<u16, String>,
+}
+
+fn parse_hex(text: &str) -> Result<u32, String> {
+    u32::from_str_radix(text, 16).map_err(|_| format!("invalid address {}", text))
+}
+
+impl Labels {
+    pub fn new() -> Self {
+        Labels {
+            names: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, address: u16, name: &str) {
+        self.names.insert(address, name.to_string());
+    }
+
+    pub fn get(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(|name| name.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// FCEUX `.nl` files, with one `$C000#Name#Comment` per line
+    pub fn load_nl(&mut self, text: &str) -> Result<(), String> {
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut fields = line.splitn(3, '#');
+            let address = fields.next().unwrap_or("");
+            let name = fields.next().unwrap_or("").trim();
+
+            // `$0300/10` names an array
+            let address = address.trim_start_matches('$');
+            let address = address.split('/').next().unwrap_or("");
+            let address = parse_hex(address).map_err(|e| format!("line {}: {}", number + 1, e))?;
+
+            if !name.is_empty() && address <= 0xffff {
+                self.insert(address as u16, name);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Mesen `.mlb` files, with one `Type:Address:Name:Comment` per line.
+    /// PRG ROM labels are given as ROM offsets, `prg_base` is the CPU
+    /// address of offset 0. Labels that don't fit in the CPU address space
+    /// are left out.
+    pub fn load_mlb(&mut self, text: &str, prg_base: u16) -> Result<(), String> {
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields = line.splitn(4, ':').collect::<Vec<_>>();
+            if fields.len() < 3 {
+                return Err(format!("line {}: missing fields", number + 1));
+            }
+
+            let name = fields[2].trim();
+            // `P:0010-001F:Table` is a range
+            let address = fields[1].split('-').next().unwrap_or("");
+            let address = parse_hex(address).map_err(|e| format!("line {}: {}", number + 1, e))?;
+
+            let base = match fields[0] {
+                "P" | "NesPrgRom" => prg_base as u32,
+                "R" | "NesInternalRam" | "G" | "NesMemory" => 0,
+                "S" | "NesSaveRam" | "W" | "NesWorkRam" => 0x6000,
+                _ => continue,
+            };
+
+            let address = base + address;
+            if !name.is_empty() && address <= 0xffff {
+                self.insert(address as u16, name);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// ca65 debug files made by `ld65 --dbgfile`. Only labels are used,
+    /// not the other symbols.
+    pub fn load_dbg(&mut self, text: &str) -> Result<(), String> {
+        for (number, line) in text.lines().enumerate() {
+            let mut parts = line.splitn(2, char::is_whitespace);
+            if parts.next() != Some("sym") {
+                continue;
+            }
+
+            let mut name = None;
+            let mut value = None;
+            let mut is_label = false;
+
+            for field in parts.next().unwrap_or("").split(',') {
+                let mut pair = field.splitn(2, '=');
+                let key = pair.next().unwrap_or("").trim();
+                let content = pair.next().unwrap_or("").trim();
+
+                match key {
+                    "name" => name = Some(content.trim_matches('"')),
+                    "val" => {
+                        let parsed = if let Some(hex) = content.strip_prefix("0x") {
+                            parse_hex(hex)
+                        } else {
+                            content
+                                .parse::<u32>()
+                                .map_err(|_| format!("invalid value {}", content))
+                        };
+                        value = Some(parsed.map_err(|e| format!("line {}: {}", number + 1, e))?);
+                    }
+                    "type" => is_label = content == "lab",
+                    _ => {}
+                }
+            }
+
+            if let (true, Some(name), Some(value)) = (is_label, name, value) {
+                if value <= 0xffff {
+                    self.insert(value as u16, name);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use crate::bus::NesMemoryMapper;
+use crate::memory::Memory;
+
+/// OAM and DMC DMA of the 2A03.
+///
+/// A DMA halts the CPU on its next read cycle, and runs from then on
+/// one CPU cycle at a time. Reads happen on get cycles and writes on put
+/// cycles, so a transfer may have to wait one cycle to align. While
+/// halted, the CPU keeps its address on the bus and the cycles that don't
+/// transfer anything read it again.
+pub struct Dma {
+    // address the CPU was about to read when it got halted
+    halt_address: Option<usize>,
+    need_halt: bool,
+    need_dummy_read: bool,
+
+    oam_running: bool,
+    oam_page: u8,
+    oam_offset: u16,
+    oam_data: Option<u8>,
+
+    dmc_running: bool,
+    dmc_address: u16,
+    dmc_sample: Option<u8>,
+}
+
+impl Default for Dma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dma {
+    pub fn new() -> Self {
+        Dma {
+            halt_address: None,
+            need_halt: false,
+            need_dummy_read: false,
+            oam_running: false,
+            oam_page: 0,
+            oam_offset: 0,
+            oam_data: None,
+            dmc_running: false,
+            dmc_address: 0,
+            dmc_sample: None,
+        }
+    }
+
+    /// Copy a page of CPU memory to OAM, as a write to $4014 does
+    pub fn start_oam(&mut self, page: u8) {
+        self.oam_running = true;
+        self.oam_page = page;
+        self.oam_offset = 0;
+        self.oam_data = None;
+        self.need_halt = true;
+    }
+
+    /// Fetch one DMC sample byte
+    pub fn start_dmc(&mut self, address: u16) {
+        self.dmc_running = true;
+        self.dmc_address = address;
+        self.need_halt = true;
+        self.need_dummy_read = true;
+    }
+
+    /// The last byte fetched for the DMC
+    pub fn take_dmc_sample(&mut self) -> Option<u8> {
+        self.dmc_sample.take()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.oam_running || self.dmc_running
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halt_address.is_some()
+    }
+
+    /// Halt the CPU on a cycle where it reads `address`. The read still
+    /// happens, but the CPU repeats it once the DMA is over.
+    pub fn halt(&mut self, memory: &mut NesMemoryMapper, address: usize) {
+        self.halt_address = Some(address);
+        self.need_halt = false;
+        memory.read(address, false);
+    }
+
+    /// Run one CPU cycle of the DMA. Get cycles are the odd ones.
+    pub fn clock(&mut self, memory: &mut NesMemoryMapper, cycle: u64) {
+        let is_get = cycle & 1 == 1;
+        let is_dmc_ready = self.dmc_running && !self.need_halt && !self.need_dummy_read;
+        self.process_cycle();
+
+        if is_get && is_dmc_ready {
+            self.dmc_sample = Some(memory.read(self.dmc_address as usize, false));
+            self.dmc_running = false;
+        } else if is_get && self.oam_running && self.oam_data.is_none() {
+            let address = (self.oam_page as usize) << 8 | self.oam_offset as usize;
+            self.oam_data = Some(memory.read(address, false));
+        } else if let (false, Some(value)) = (is_get, self.oam_data) {
+            memory.write_oam(value);
+            self.oam_data = None;
+            self.oam_offset += 1;
+
+            if self.oam_offset == 0x100 {
+                self.oam_running = false;
+            }
+        } else {
+            // waiting for the halt, the DMC dummy cycle or alignment
+            self.dummy_read(memory);
+        }
+
+        if !self.is_running() {
+            self.halt_address = None;
+        }
+    }
+
+    // A DMC DMA started during OAM DMA still goes through its halt and
+    // dummy cycles, while OAM DMA carries on
+    fn process_cycle(&mut self) {
+        if self.need_halt {
+            self.need_halt = false;
+        } else if self.need_dummy_read {
+            self.need_dummy_read = false;
+        }
+    }
+
+    // The controller ports are only clocked once while the halted CPU
+    // keeps reading them, other addresses see every read
+    fn dummy_read(&mut self, memory: &mut NesMemoryMapper) {
+        if let Some(address) = self.halt_address {
+            if address != 0x4016 && address != 0x4017 {
+                memory.read(address, false);
+            }
+        }
+    }
+}
@@ -4,13 +4,19 @@ extern crate bitflags;
 #[macro_use]
 pub mod macros;
 
+pub mod apu;
 pub mod bus;
 pub mod cartridge;
 pub mod controller;
 pub mod cpu;
+pub mod disasm;
+pub mod dma;
 pub mod mappers;
 pub mod memory;
+pub mod ntsc;
+pub mod palette;
 pub mod ppu;
+pub mod region;
 pub mod utils;
 
 pub use cartridge::CartridgeRef;
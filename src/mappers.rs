@@ -21,6 +21,11 @@ pub trait Mapper {
         mapped_address: &mut usize,
         value: u8,
     ) -> MapperStatus;
+
+    /// Whether the mapper is holding the IRQ line
+    fn irq_pending(&self) -> bool {
+        false
+    }
 }
 
 pub struct NROM {
@@ -0,0 +1,213 @@
+use crate::palette::{composite_signal, yiq_to_rgb};
+use crate::utils::*;
+use std::f32::consts::PI;
+
+// the PPU outputs 8 samples per pixel, the color subcarrier lasts 12 samples
+const SAMPLES_PER_PIXEL: usize = 8;
+const PHASES: usize = 12;
+
+/// How the composite signal is decoded by the simulated TV.
+#[derive(Debug, Copy, Clone)]
+pub struct NtscSetup {
+    /// Number of signal samples averaged to get the luma of an output pixel.
+    /// Less than 12 lets chroma leak into luma (dot crawl).
+    pub luma_width: usize,
+    /// Number of signal samples averaged to get the chroma of an output pixel.
+    /// Wider values bleed colors into their neighbours.
+    pub chroma_width: usize,
+    /// Luma and chroma travel on separate wires (S-Video), so they can't
+    /// interfere with each other
+    pub separate_chroma: bool,
+    /// Skip the signal entirely and show every pixel in its flat color
+    pub rgb: bool,
+    pub saturation: f32,
+    /// Hue rotation in degrees
+    pub hue: f32,
+    pub gamma: f32,
+}
+
+impl NtscSetup {
+    pub fn composite() -> NtscSetup {
+        NtscSetup {
+            luma_width: 12,
+            chroma_width: 24,
+            separate_chroma: false,
+            rgb: false,
+            saturation: 1.0,
+            hue: 0.0,
+            gamma: 1.8,
+        }
+    }
+
+    pub fn svideo() -> NtscSetup {
+        NtscSetup {
+            luma_width: 6,
+            chroma_width: 24,
+            separate_chroma: true,
+            ..NtscSetup::composite()
+        }
+    }
+
+    pub fn rgb() -> NtscSetup {
+        NtscSetup {
+            rgb: true,
+            ..NtscSetup::composite()
+        }
+    }
+}
+
+/// Simulates the NTSC signal of the PPU and decodes it the way a TV would,
+/// producing the dot crawl, color fringing and artifact colors of real hardware.
+///
+/// The output is wider than the PPU frame: every 3 input pixels become 7
+/// output pixels, which keeps the 8:7 pixel aspect ratio of NTSC.
+pub struct NtscFilter {
+    setup: NtscSetup,
+    // signal level of every pixel value at every subcarrier phase
+    levels: Vec<[f32; PHASES]>,
+    // average level of every pixel value over a full subcarrier cycle
+    luma: Vec<f32>,
+    cos: [f32; PHASES],
+    sin: [f32; PHASES],
+    hue_cos: f32,
+    hue_sin: f32,
+}
+
+impl NtscFilter {
+    pub fn new(setup: NtscSetup) -> NtscFilter {
+        let levels: Vec<[f32; PHASES]> = (0..0x200)
+            .map(|pixel| {
+                let mut levels = [0.0; PHASES];
+                for (phase, level) in levels.iter_mut().enumerate() {
+                    *level = composite_signal(pixel, phase);
+                }
+                levels
+            })
+            .collect();
+
+        let luma = levels
+            .iter()
+            .map(|levels| levels.iter().sum::<f32>() / PHASES as f32)
+            .collect();
+
+        let mut cos = [0.0; PHASES];
+        let mut sin = [0.0; PHASES];
+
+        for phase in 0..PHASES {
+            let angle = PI / 6.0 * phase as f32;
+            cos[phase] = angle.cos();
+            sin[phase] = angle.sin();
+        }
+
+        let (hue_sin, hue_cos) = setup.hue.to_radians().sin_cos();
+
+        NtscFilter {
+            setup,
+            levels,
+            luma,
+            cos,
+            sin,
+            hue_cos,
+            hue_sin,
+        }
+    }
+
+    pub fn setup(&self) -> NtscSetup {
+        self.setup
+    }
+
+    pub fn output_width(input_width: usize) -> usize {
+        ((input_width - 1) / 3 + 1) * 7
+    }
+
+    /// Filter `frame` into `screen`, which must be `output_width` pixels
+    /// wide. `phases` is the subcarrier phase (0-2) at the start of every
+    /// scanline, as given by `PPU::scanline_phases`.
+    pub fn render(&self, frame: &IndexedScreen, phases: &[u8], screen: &mut Screen) {
+        let width = NtscFilter::output_width(frame.width());
+
+        assert_eq!(screen.width(), width);
+        assert_eq!(screen.height(), frame.height());
+        assert!(phases.len() >= frame.height());
+
+        let samples = frame.width() * SAMPLES_PER_PIXEL;
+
+        let mut sum_y = vec![0.0; samples + 1];
+        let mut sum_i = vec![0.0; samples + 1];
+        let mut sum_q = vec![0.0; samples + 1];
+
+        for (y, &phase) in phases.iter().enumerate().take(frame.height()) {
+            if self.setup.rgb {
+                for x in 0..width {
+                    let pixel = frame.get_pixel(x * frame.width() / width, y) as usize;
+                    let (luma, i, q) = self.decode_flat(pixel);
+                    screen.set_pixel(x, y, self.to_rgb(luma, i, q));
+                }
+                continue;
+            }
+
+            let line_phase = (phase as usize % 3) * 4;
+
+            for sample in 0..samples {
+                let pixel = (frame.get_pixel(sample / SAMPLES_PER_PIXEL, y) & 0x1ff) as usize;
+                let phase = (line_phase + sample) % PHASES;
+                let signal = self.levels[pixel][phase];
+
+                let (luma, chroma) = if self.setup.separate_chroma {
+                    (self.luma[pixel], signal - self.luma[pixel])
+                } else {
+                    (signal, signal)
+                };
+
+                sum_y[sample + 1] = sum_y[sample] + luma;
+                sum_i[sample + 1] = sum_i[sample] + chroma * self.cos[phase];
+                sum_q[sample + 1] = sum_q[sample] + chroma * self.sin[phase];
+            }
+
+            for x in 0..width {
+                let center = (x * samples + samples / 2) / width;
+
+                let luma = average(&sum_y, center, self.setup.luma_width);
+                let i = average(&sum_i, center, self.setup.chroma_width);
+                let q = average(&sum_q, center, self.setup.chroma_width);
+
+                screen.set_pixel(x, y, self.to_rgb(luma, i, q));
+            }
+        }
+    }
+
+    fn decode_flat(&self, pixel: usize) -> (f32, f32, f32) {
+        let levels = &self.levels[pixel & 0x1ff];
+        let mut i = 0.0;
+        let mut q = 0.0;
+
+        for (level, (cos, sin)) in levels.iter().zip(self.cos.iter().zip(self.sin.iter())) {
+            i += level * cos;
+            q += level * sin;
+        }
+
+        (
+            self.luma[pixel & 0x1ff],
+            i / PHASES as f32,
+            q / PHASES as f32,
+        )
+    }
+
+    fn to_rgb(&self, luma: f32, i: f32, q: f32) -> PPUColor {
+        let saturation = self.setup.saturation;
+        let rotated_i = (i * self.hue_cos - q * self.hue_sin) * saturation;
+        let rotated_q = (i * self.hue_sin + q * self.hue_cos) * saturation;
+
+        yiq_to_rgb(luma, rotated_i, rotated_q, self.setup.gamma)
+    }
+}
+
+// average of the samples in a `width` wide window around `center`,
+// from the running sums in `sums`
+fn average(sums: &[f32], center: usize, width: usize) -> f32 {
+    let samples = sums.len() - 1;
+    let start = center.saturating_sub(width / 2);
+    let end = (start + width.max(1)).min(samples);
+
+    (sums[end] - sums[start]) / (end - start) as f32
+}
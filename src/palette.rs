@@ -0,0 +1,473 @@
+use crate::ppu::PPU_COLORS;
+use crate::utils::*;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+// the measured attenuation of a 2C02 on the channels that are not emphasized
+const EMPHASIS_ATTENUATION: f32 = 0.816328;
+
+static FCEUX_COLORS: [PPUColor; 0x40] = [
+    (0x74, 0x74, 0x74),
+    (0x24, 0x18, 0x8c),
+    (0x00, 0x00, 0xa8),
+    (0x44, 0x00, 0x9c),
+    (0x8c, 0x00, 0x74),
+    (0xa8, 0x00, 0x10),
+    (0xa4, 0x00, 0x00),
+    (0x7c, 0x08, 0x00),
+    (0x40, 0x2c, 0x00),
+    (0x00, 0x44, 0x00),
+    (0x00, 0x50, 0x00),
+    (0x00, 0x3c, 0x14),
+    (0x18, 0x3c, 0x5c),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0xbc, 0xbc, 0xbc),
+    (0x00, 0x70, 0xec),
+    (0x20, 0x38, 0xec),
+    (0x80, 0x00, 0xf0),
+    (0xbc, 0x00, 0xbc),
+    (0xe4, 0x00, 0x58),
+    (0xd8, 0x28, 0x00),
+    (0xc8, 0x4c, 0x0c),
+    (0x88, 0x70, 0x00),
+    (0x00, 0x94, 0x00),
+    (0x00, 0xa8, 0x00),
+    (0x00, 0x90, 0x38),
+    (0x00, 0x80, 0x88),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0xfc, 0xfc, 0xfc),
+    (0x3c, 0xbc, 0xfc),
+    (0x5c, 0x94, 0xfc),
+    (0xcc, 0x88, 0xfc),
+    (0xf4, 0x78, 0xfc),
+    (0xfc, 0x74, 0xb4),
+    (0xfc, 0x74, 0x60),
+    (0xfc, 0x98, 0x38),
+    (0xf0, 0xbc, 0x3c),
+    (0x80, 0xd0, 0x10),
+    (0x4c, 0xdc, 0x48),
+    (0x58, 0xf8, 0x98),
+    (0x00, 0xe8, 0xd8),
+    (0x78, 0x78, 0x78),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0xfc, 0xfc, 0xfc),
+    (0xa8, 0xe4, 0xfc),
+    (0xc4, 0xd4, 0xfc),
+    (0xd4, 0xc8, 0xfc),
+    (0xfc, 0xc4, 0xfc),
+    (0xfc, 0xc4, 0xd8),
+    (0xfc, 0xbc, 0xb0),
+    (0xfc, 0xd8, 0xa8),
+    (0xfc, 0xe4, 0xa0),
+    (0xe0, 0xfc, 0xa0),
+    (0xa8, 0xf0, 0xbc),
+    (0xb0, 0xfc, 0xcc),
+    (0x9c, 0xfc, 0xf0),
+    (0xc4, 0xc4, 0xc4),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+];
+
+// the 64 base colors of FirebrandX's Smooth palette (Smooth_FBX.pal)
+static SMOOTH_COLORS: [PPUColor; 0x40] = [
+    (0x6a, 0x6d, 0x6a),
+    (0x00, 0x13, 0x80),
+    (0x1e, 0x00, 0x8a),
+    (0x39, 0x00, 0x7a),
+    (0x55, 0x00, 0x56),
+    (0x5a, 0x00, 0x18),
+    (0x4f, 0x10, 0x00),
+    (0x3d, 0x1c, 0x00),
+    (0x25, 0x32, 0x00),
+    (0x00, 0x3d, 0x00),
+    (0x00, 0x40, 0x00),
+    (0x00, 0x39, 0x24),
+    (0x00, 0x2e, 0x55),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0xb9, 0xbc, 0xb9),
+    (0x18, 0x50, 0xc7),
+    (0x4b, 0x30, 0xe3),
+    (0x73, 0x22, 0xd6),
+    (0x95, 0x1f, 0xa9),
+    (0x9d, 0x28, 0x5c),
+    (0x98, 0x37, 0x00),
+    (0x7f, 0x4c, 0x00),
+    (0x5e, 0x64, 0x00),
+    (0x22, 0x77, 0x00),
+    (0x02, 0x7e, 0x02),
+    (0x00, 0x76, 0x45),
+    (0x00, 0x6e, 0x8a),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0xff, 0xff, 0xff),
+    (0x68, 0xa6, 0xff),
+    (0x8c, 0x9c, 0xff),
+    (0xb5, 0x86, 0xff),
+    (0xd9, 0x75, 0xfd),
+    (0xe3, 0x77, 0xb9),
+    (0xe5, 0x8d, 0x68),
+    (0xd4, 0x9d, 0x29),
+    (0xb3, 0xaf, 0x0c),
+    (0x7b, 0xc2, 0x11),
+    (0x55, 0xca, 0x47),
+    (0x46, 0xcb, 0x81),
+    (0x47, 0xc1, 0xc5),
+    (0x4a, 0x4d, 0x4a),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+    (0xff, 0xff, 0xff),
+    (0xcc, 0xea, 0xff),
+    (0xdd, 0xde, 0xff),
+    (0xec, 0xda, 0xff),
+    (0xf8, 0xd7, 0xfe),
+    (0xfc, 0xd6, 0xf5),
+    (0xfd, 0xdb, 0xcf),
+    (0xf9, 0xe7, 0xb5),
+    (0xf1, 0xf0, 0xaa),
+    (0xda, 0xfa, 0xa9),
+    (0xc9, 0xff, 0xbc),
+    (0xc3, 0xfb, 0xd7),
+    (0xc4, 0xf6, 0xf6),
+    (0xbe, 0xc1, 0xbe),
+    (0x00, 0x00, 0x00),
+    (0x00, 0x00, 0x00),
+];
+
+// 3 bits per channel, written as octal RGB digits like the RGB PPU documentation
+static RGB_PPU_COLORS: [u16; 0x40] = [
+    0o333, 0o014, 0o006, 0o326, 0o403, 0o503, 0o510, 0o420, //
+    0o320, 0o120, 0o031, 0o040, 0o022, 0o000, 0o000, 0o000, //
+    0o555, 0o036, 0o027, 0o407, 0o507, 0o704, 0o700, 0o630, //
+    0o430, 0o140, 0o040, 0o053, 0o044, 0o000, 0o000, 0o000, //
+    0o777, 0o357, 0o447, 0o637, 0o707, 0o737, 0o740, 0o750, //
+    0o660, 0o360, 0o070, 0o276, 0o077, 0o000, 0o000, 0o000, //
+    0o777, 0o567, 0o657, 0o757, 0o747, 0o755, 0o764, 0o772, //
+    0o773, 0o572, 0o473, 0o276, 0o467, 0o000, 0o000, 0o000, //
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PalettePreset {
+    /// The palette nesrs always used (`PPU_COLORS`)
+    #[default]
+    Classic,
+    /// Decoded from the measured composite signal levels of a 2C02
+    Ntsc2C02,
+    /// FCEUX's default palette
+    Fceux,
+    /// Generated like Nestopia's YUV decoder
+    NestopiaYuv,
+    /// FirebrandX's Smooth palette
+    Smooth,
+    /// The 2C03 RGB PPU used in PlayChoice-10 and some Vs. System boards
+    Rgb2C03,
+    /// The 2C05 RGB PPU. It shares the 2C03 color set, so it is the same
+    /// palette under the name of the other chip.
+    Rgb2C05,
+}
+
+impl PalettePreset {
+    pub const ALL: [PalettePreset; 7] = [
+        PalettePreset::Classic,
+        PalettePreset::Ntsc2C02,
+        PalettePreset::Fceux,
+        PalettePreset::NestopiaYuv,
+        PalettePreset::Smooth,
+        PalettePreset::Rgb2C03,
+        PalettePreset::Rgb2C05,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PalettePreset::Classic => "Classic",
+            PalettePreset::Ntsc2C02 => "2C02 (measured)",
+            PalettePreset::Fceux => "FCEUX",
+            PalettePreset::NestopiaYuv => "Nestopia YUV",
+            PalettePreset::Smooth => "Smooth (FirebrandX)",
+            PalettePreset::Rgb2C03 => "2C03 (RGB)",
+            PalettePreset::Rgb2C05 => "2C05 (RGB, same as 2C03)",
+        }
+    }
+}
+
+/// Converts the NES pixel values produced by the PPU into RGB colors.
+///
+/// A palette holds one color for each of the 512 possible pixel values
+/// (64 color indices times 8 emphasis combinations).
+#[derive(Clone)]
+pub struct Palette {
+    colors: Vec<PPUColor>,
+}
+
+impl Palette {
+    pub fn new() -> Palette {
+        Palette::from_base_colors(&PPU_COLORS)
+    }
+
+    /// Build a palette from 64 base colors, deriving the emphasized colors
+    /// by darkening the channels that are not emphasized
+    pub fn from_base_colors(base: &[PPUColor]) -> Palette {
+        assert_eq!(base.len(), 0x40);
+
+        let mut colors = Vec::with_capacity(0x200);
+
+        for emphasis in 0..8 {
+            for &(r, g, b) in base.iter() {
+                colors.push(emphasize((r, g, b), emphasis));
+            }
+        }
+
+        Palette { colors }
+    }
+
+    pub fn from_preset(preset: PalettePreset) -> Palette {
+        match preset {
+            PalettePreset::Classic => Palette::from_base_colors(&PPU_COLORS),
+            PalettePreset::Ntsc2C02 => Palette::from_composite_signal(1.0, 1.8),
+            PalettePreset::Fceux => Palette::from_base_colors(&FCEUX_COLORS),
+            PalettePreset::NestopiaYuv => Palette::from_yuv_decoder(),
+            PalettePreset::Smooth => Palette::from_base_colors(&SMOOTH_COLORS),
+            PalettePreset::Rgb2C03 | PalettePreset::Rgb2C05 => Palette::from_rgb_ppu(),
+        }
+    }
+
+    /// Parse a `.pal` file: either 64 colors (192 bytes), or 512 colors
+    /// (1536 bytes) covering every emphasis combination
+    pub fn from_pal(bytes: &[u8]) -> Result<Palette, String> {
+        let colors: Vec<PPUColor> = bytes
+            .chunks_exact(3)
+            .map(|rgb| (rgb[0], rgb[1], rgb[2]))
+            .collect();
+
+        match bytes.len() {
+            192 => Ok(Palette::from_base_colors(&colors)),
+            1536 => Ok(Palette { colors }),
+            len => Err(format!(
+                "Invalid palette size: {} bytes (expected 192 or 1536)",
+                len
+            )),
+        }
+    }
+
+    /// Serialize all 512 colors as a 1536 byte `.pal` file
+    pub fn to_pal(&self) -> Vec<u8> {
+        self.colors
+            .iter()
+            .flat_map(|&(r, g, b)| vec![r, g, b])
+            .collect()
+    }
+
+    // Demodulate the square wave the 2C02 outputs for every pixel value,
+    // using the signal levels measured relative to sync
+    fn from_composite_signal(saturation: f32, gamma: f32) -> Palette {
+        let colors = (0..0x200)
+            .map(|pixel| {
+                let (y, i, q) = composite_yiq(pixel);
+                yiq_to_rgb(y, i * saturation, q * saturation, gamma)
+            })
+            .collect();
+
+        Palette { colors }
+    }
+
+    // Nestopia's YUV mode models the chroma as a sine of the hue angle
+    // around the average of the two signal levels and decodes it with
+    // the YUV matrix
+    fn from_yuv_decoder() -> Palette {
+        const LEVELS: [[f32; 4]; 2] = [[-0.12, 0.00, 0.31, 0.72], [0.40, 0.68, 1.00, 1.00]];
+        const EMPHASIS_HUES: [f32; 8] = [0.0, 6.0, 10.0, 8.0, 2.0, 4.0, 0.0, 0.0];
+
+        let colors = (0..0x200)
+            .map(|pixel: usize| {
+                let color = pixel & 0x0f;
+                let mut level = [LEVELS[0][(pixel >> 4) & 3], LEVELS[1][(pixel >> 4) & 3]];
+
+                if color == 0x00 {
+                    level[0] = level[1];
+                } else if color == 0x0d {
+                    level[1] = level[0];
+                } else if color > 0x0d {
+                    level = [0.0, 0.0];
+                }
+
+                let mut y = (level[0] + level[1]) * 0.5;
+                let mut saturation = (level[1] - level[0]) * 0.5;
+                let hue = PI / 6.0 * (color as f32 - 3.0);
+                let mut i = hue.sin() * saturation;
+                let mut q = hue.cos() * saturation;
+
+                let emphasis = pixel >> 6;
+
+                if emphasis == 7 && color <= 0x0d {
+                    y = y * 0.79399 - 0.0782838;
+                } else if emphasis > 0 && color <= 0x0d {
+                    saturation = 0.1 * 0.79399;
+                    y -= saturation * 0.5;
+
+                    if emphasis >= 3 && emphasis != 4 {
+                        saturation *= 0.6;
+                        y -= saturation;
+                    }
+
+                    let hue = PI / 6.0 * (EMPHASIS_HUES[emphasis] - 3.0);
+                    i += hue.sin() * saturation;
+                    q += hue.cos() * saturation;
+                }
+
+                // the I/Q axes are the U/V axes rotated by 33 degrees
+                let (sin, cos) = (33.0 * PI / 180.0).sin_cos();
+                let u = -sin * i + cos * q;
+                let v = cos * i + sin * q;
+
+                let r = y + 1.13983 * v;
+                let g = y - 0.39465 * u - 0.58060 * v;
+                let b = y + 2.03211 * u;
+
+                (to_channel(r, 2.2), to_channel(g, 2.2), to_channel(b, 2.2))
+            })
+            .collect();
+
+        Palette { colors }
+    }
+
+    // RGB PPUs don't darken the other channels on emphasis,
+    // they drive the emphasized channel to full brightness
+    fn from_rgb_ppu() -> Palette {
+        let mut colors = Vec::with_capacity(0x200);
+
+        for emphasis in 0..8 {
+            for &color in RGB_PPU_COLORS.iter() {
+                let mut channels = [(color >> 6) & 7, (color >> 3) & 7, color & 7];
+
+                for (bit, channel) in channels.iter_mut().enumerate() {
+                    if emphasis & (1 << bit) > 0 {
+                        *channel = 7;
+                    }
+                }
+
+                let scale = |value: u16| (value * 255 / 7) as u8;
+                colors.push((scale(channels[0]), scale(channels[1]), scale(channels[2])));
+            }
+        }
+
+        Palette { colors }
+    }
+
+    pub fn color(&self, pixel: u16) -> PPUColor {
+        self.colors[(pixel & 0x1ff) as usize]
+    }
+
+    pub fn render(&self, frame: &IndexedScreen, screen: &mut Screen) {
+        assert_eq!(frame.width(), screen.width());
+        assert_eq!(frame.height(), screen.height());
+
+        for y in 0..frame.height() {
+            for x in 0..frame.width() {
+                screen.set_pixel(x, y, self.color(frame.get_pixel(x, y)));
+            }
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::new()
+    }
+}
+
+fn emphasize(color: PPUColor, emphasis: usize) -> PPUColor {
+    if emphasis == 0 {
+        return color;
+    }
+
+    let mut channels = [color.0 as f32, color.1 as f32, color.2 as f32];
+
+    // red, green and blue emphasis darken the other two channels
+    for (bit, channel) in [(0x01, 0), (0x02, 1), (0x04, 2)].iter() {
+        if emphasis & bit > 0 {
+            for (other, value) in channels.iter_mut().enumerate() {
+                if other != *channel {
+                    *value *= EMPHASIS_ATTENUATION;
+                }
+            }
+        }
+    }
+
+    (channels[0] as u8, channels[1] as u8, channels[2] as u8)
+}
+
+// voltage levels relative to sync, as measured on a 2C02
+const SIGNAL_BLACK: f32 = 0.518;
+const SIGNAL_WHITE: f32 = 1.962;
+const SIGNAL_ATTENUATION: f32 = 0.746;
+const SIGNAL_LEVELS: [f32; 8] = [
+    0.350, 0.518, 0.962, 1.550, // signal low
+    1.094, 1.506, 1.962, 1.962, // signal high
+];
+
+/// The normalized composite signal level the PPU outputs for `pixel`
+/// at one of the 12 phases of the color subcarrier
+pub(crate) fn composite_signal(pixel: usize, phase: usize) -> f32 {
+    let in_color_phase = |color: usize| (color + phase + 8) % 12 < 6;
+
+    let color = pixel & 0x0f;
+    let level = if color < 0x0e { (pixel >> 4) & 3 } else { 1 };
+    let low = SIGNAL_LEVELS[level + if color == 0x00 { 4 } else { 0 }];
+    let high = SIGNAL_LEVELS[level + if color < 0x0d { 4 } else { 0 }];
+
+    let mut signal = if in_color_phase(color) { high } else { low };
+
+    // emphasis attenuates the signal during the red, green or blue phase
+    if (pixel & 0x40 > 0 && in_color_phase(0x0c))
+        || (pixel & 0x80 > 0 && in_color_phase(0x04))
+        || (pixel & 0x100 > 0 && in_color_phase(0x08))
+    {
+        signal *= SIGNAL_ATTENUATION;
+    }
+
+    (signal - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK)
+}
+
+fn composite_yiq(pixel: usize) -> (f32, f32, f32) {
+    let mut y = 0.0;
+    let mut i = 0.0;
+    let mut q = 0.0;
+
+    for phase in 0..12 {
+        let signal = composite_signal(pixel, phase) / 12.0;
+        let angle = PI / 6.0 * phase as f32;
+
+        y += signal;
+        i += signal * angle.cos();
+        q += signal * angle.sin();
+    }
+
+    (y, i, q)
+}
+
+/// Convert YIQ to RGB with the FCC conversion matrix
+pub(crate) fn yiq_to_rgb(y: f32, i: f32, q: f32, gamma: f32) -> PPUColor {
+    (
+        to_channel(y + 0.946882 * i + 0.623557 * q, gamma),
+        to_channel(y - 0.274788 * i - 0.635691 * q, gamma),
+        to_channel(y - 1.108545 * i + 1.709007 * q, gamma),
+    )
+}
+
+// TVs expect a 2.2 gamma, `gamma` is the one the decoded signal already has
+fn to_channel(value: f32, gamma: f32) -> u8 {
+    let value = if value <= 0.0 {
+        0.0
+    } else {
+        value.powf(2.2 / gamma)
+    };
+
+    (value * 255.0).clamp(0.0, 255.0) as u8
+}
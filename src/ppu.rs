@@ -1,5 +1,7 @@
 use crate::cartridge::*;
 use crate::memory::Memory;
+use crate::palette::Palette;
+use crate::region::Region;
 use crate::utils::*;
 use std::convert::{From, Into};
 use std::fmt::Write;
@@ -230,6 +232,14 @@ bitflags! {
 }
 
 impl PPUMask {
+    pub fn is_greyscale(&self) -> bool {
+        self.contains(PPUMask::GREYSCALE)
+    }
+
+    pub fn emphasis(&self) -> u16 {
+        ((self.bits & 0xe0) as u16) << 1
+    }
+
     pub fn is_render_bg(&self) -> bool {
         self.contains(PPUMask::SHOW_BG)
     }
@@ -271,6 +281,105 @@ impl PPUControl {
     }
 }
 
+// NES color used to mark pixels where sprite 0 overlaps the background
+const SPRITE0_HIT_HIGHLIGHT: u16 = 0x16;
+
+// Color of the sprite bounding boxes drawn over the screen
+const SPRITE_BOX_COLOR: PPUColor = (0, 255, 0);
+
+/// Debug switches that only change what is drawn on the screen, the
+/// state the game can observe is never affected
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct RenderDebug {
+    pub hide_background: bool,
+    pub hide_sprites: bool,
+    /// Draw the left 8 pixels even when PPUMASK hides them
+    pub show_left_column: bool,
+    pub sprite0_only: bool,
+    pub highlight_sprite0_hit: bool,
+    /// Outline every sprite in OAM at the end of the frame
+    pub sprite_boxes: bool,
+}
+
+/// One OAM entry as shown by the sprite viewer
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpriteInfo {
+    pub index: usize,
+    pub x: u8,
+    pub y: u8,
+    pub tile: u8,
+    pub palette: u8,
+    pub behind_background: bool,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    /// Pattern table address of the (top) tile
+    pub pattern_address: u16,
+    pub height: usize,
+}
+
+// Color of the scroll viewport drawn over the nametable viewer
+const SCROLL_OVERLAY_COLOR: PPUColor = (255, 0, 255);
+
+/// What the nametable viewer shows at a tile
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NametableTileInfo {
+    pub nametable: usize,
+    pub tile_x: usize,
+    pub tile_y: usize,
+    pub tile_id: u8,
+    pub address: u16,
+    pub attribute_address: u16,
+    pub attribute: u8,
+    pub palette: u8,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PPUEventKind {
+    RegisterRead,
+    RegisterWrite,
+    OamDma,
+    Nmi,
+    MapperIrq,
+}
+
+/// Something that happened to the PPU, with the dot it happened on and
+/// the address of the CPU instruction responsible for it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PPUEvent {
+    pub kind: PPUEventKind,
+    pub scanline: i32,
+    pub cycle: i32,
+    pub address: u16,
+    pub value: u8,
+    pub pc: u16,
+}
+
+impl PPUEvent {
+    fn color(&self) -> PPUColor {
+        let color = match self.kind {
+            PPUEventKind::Nmi => (255, 255, 255),
+            PPUEventKind::MapperIrq => (255, 255, 0),
+            PPUEventKind::OamDma => (0, 255, 255),
+            _ => match self.address & 0x07 {
+                0 => (255, 64, 64),
+                1 => (64, 255, 64),
+                2 => (255, 160, 0),
+                3 | 4 => (160, 96, 255),
+                5 => (64, 128, 255),
+                6 => (255, 96, 255),
+                _ => (200, 160, 120),
+            },
+        };
+
+        // reads are drawn darker than writes
+        if self.kind == PPUEventKind::RegisterRead {
+            (color.0 / 2, color.1 / 2, color.2 / 2)
+        } else {
+            color
+        }
+    }
+}
+
 enum AddressLatch {
     Lo,
     Hi,
@@ -314,7 +423,9 @@ impl ShiftRegister16 {
 enum PPUSpriteRead {
     ReadY,
     ReadRest,
-    OnSpriteOverflow,
+    CheckOverflow,
+    ReadOverflowRest(u8),
+    Done,
 }
 
 pub struct PPU {
@@ -324,7 +435,6 @@ pub struct PPU {
     palette_table: [u8; 32],          // 0x3f00 - 0x3fff
 
     pub oam_address: u8,
-    oam_address_loop_counter: u32,
     pub oams: [u8; 256],
     internal_oams: [u8; 64],
     next_scanline_oams: [u8; 64],
@@ -337,13 +447,20 @@ pub struct PPU {
     next_scanline_is_sprite0_hit_possible: bool,
     is_sprite0_hit_being_rendered: bool,
 
+    frame: IndexedScreen,
     screen: Screen,
+    palette: Palette,
+    region: Region,
+    scanline_phases: Vec<u8>,
+    dot_phase: u8,
     cycle: i32,
     scanline: i32,
     odd_cycle: bool,
     supress_vblank: bool,
     pub done_drawing: bool,
     pub call_nmi: bool,
+    prev_nmi_output: bool,
+    nmi_triggered: bool,
 
     status: PPUStatus,
     control: PPUControl,
@@ -354,6 +471,7 @@ pub struct PPU {
 
     temp_address: PPUAddress,
     vaddress: PPUAddress,
+    vaddress_update_delay: u8,
     bg_next_tile_id: u8,
     bg_next_tile_attrib: u8,
     bg_next_tile_lsb: u8,
@@ -363,8 +481,20 @@ pub struct PPU {
     bg_attrib_shifter: ShiftRegister16,
     sprite_pattern_shifter: Vec<ShiftRegister16>,
 
+    no_sprite_limit: bool,
+    render_debug: RenderDebug,
+    display_oams: [u8; 256],
+    display_sprite_count: usize,
+    display_sprite_pattern_shifter: Vec<ShiftRegister16>,
+
     // for debug
     pub screen_debug_pattern: [Screen; 2],
+    pub screen_debug_nametable: Screen,
+    pub screen_debug_sprites: Screen,
+    pub screen_debug_events: Screen,
+    event_recording: bool,
+    events: Vec<PPUEvent>,
+    frame_events: Vec<PPUEvent>,
 }
 
 impl Memory for PPU {
@@ -378,7 +508,11 @@ impl Memory for PPU {
                 if !is_read_only {
                     self.address_latch = AddressLatch::Hi;
                     self.status.set(PPUStatus::VBLANK, false);
-                    self.supress_vblank = true;
+
+                    // reading one dot before VBL is set keeps it from being set
+                    if self.scanline == self.region.vblank_scanline() && self.cycle == 1 {
+                        self.supress_vblank = true;
+                    }
                 }
 
                 status
@@ -470,7 +604,8 @@ impl Memory for PPU {
                     self.temp_address
                         .set_address((self.temp_address.address() & 0xff00) | (value as usize));
                     self.address_latch = AddressLatch::Hi;
-                    self.vaddress.set_address(self.temp_address.address());
+                    // v is only updated a few dots after the write
+                    self.vaddress_update_delay = 3;
                 }
             },
             PPUDATA => {
@@ -482,6 +617,31 @@ impl Memory for PPU {
     }
 }
 
+// Find the first opaque sprite pixel at the current dot, returning the
+// pixel, the sprite attributes and the sprite index in the list
+fn front_sprite_pixel(
+    oams: &[u8],
+    shifters: &[ShiftRegister16],
+    sprite_count: usize,
+) -> (usize, u8, usize) {
+    let mut result = (0, 0, 0);
+
+    for (sprite_index, shifter) in shifters.iter().enumerate().take(sprite_count) {
+        let sprite_offset = sprite_index << 2;
+
+        if oams[sprite_offset + 3] == 0 {
+            let pixel = shifter.get(8);
+            result = (pixel, oams[sprite_offset + 2], sprite_index);
+
+            if pixel != 0 {
+                break;
+            }
+        }
+    }
+
+    result
+}
+
 impl PPU {
     pub fn new(cartridge: CartridgeRef) -> PPU {
         PPU {
@@ -497,18 +657,24 @@ impl PPU {
             sprite_count: 0,
             curr_oam_data: 0,
             internal_oam_address: 0,
-            oam_address_loop_counter: 0,
             sprite_read_mode: PPUSpriteRead::ReadY,
             is_sprite0_hit_possible: false,
             next_scanline_is_sprite0_hit_possible: false,
             is_sprite0_hit_being_rendered: false,
 
+            frame: IndexedScreen::new(NES_WIDTH_SIZE, NES_HEIGHT_SIZE),
             screen: Screen::new(NES_WIDTH_SIZE, NES_HEIGHT_SIZE),
+            palette: Palette::new(),
+            region: Region::Ntsc,
+            scanline_phases: vec![0; NES_HEIGHT_SIZE],
+            dot_phase: 0,
             cycle: 0,
             scanline: 0,
             odd_cycle: false,
             done_drawing: false,
             call_nmi: false,
+            prev_nmi_output: false,
+            nmi_triggered: false,
             supress_vblank: false,
 
             status: PPUStatus::empty(),
@@ -517,6 +683,7 @@ impl PPU {
             address_latch: AddressLatch::Hi,
             temp_address: PPUAddress::from(0),
             vaddress: PPUAddress::from(0),
+            vaddress_update_delay: 0,
             data_buffer: 0,
             fine_x: 0,
             bg_next_tile_id: 0,
@@ -528,7 +695,19 @@ impl PPU {
             bg_attrib_shifter: ShiftRegister16::new(),
             sprite_pattern_shifter: vec![ShiftRegister16::new(); 8],
 
+            no_sprite_limit: false,
+            render_debug: RenderDebug::default(),
+            display_oams: [0; 256],
+            display_sprite_count: 0,
+            display_sprite_pattern_shifter: vec![ShiftRegister16::new(); 64],
+
             screen_debug_pattern: [Screen::new(128, 128), Screen::new(128, 128)],
+            screen_debug_nametable: Screen::new(512, 480),
+            screen_debug_sprites: Screen::new(64, 128),
+            screen_debug_events: Screen::new(341, 262),
+            event_recording: false,
+            events: Vec::new(),
+            frame_events: Vec::new(),
         }
     }
 
@@ -550,6 +729,200 @@ impl PPU {
             });
     }
 
+    // Sprite evaluation walks OAM with OAMADDR, where n is the sprite index
+    // (bits 2-7) and m the byte offset (bits 0-1). Once the secondary OAM is
+    // full, a miss increments both n and m, which is the hardware bug that
+    // makes the overflow flag unreliable.
+    fn evaluate_sprite(&mut self, sprite_size: i32) {
+        let diff = self.scanline - (self.curr_oam_data as i32);
+        let is_in_range = diff >= 0 && diff < sprite_size;
+
+        match self.sprite_read_mode {
+            PPUSpriteRead::ReadY => {
+                self.internal_oams[self.internal_oam_address] = self.curr_oam_data;
+
+                if is_in_range {
+                    if self.cycle == 66 {
+                        self.is_sprite0_hit_possible = true;
+                    }
+
+                    self.sprite_read_mode = PPUSpriteRead::ReadRest;
+                    self.oam_address = self.oam_address.wrapping_add(1);
+                    self.internal_oam_address += 1;
+                } else {
+                    self.oam_address = self.oam_address.wrapping_add(4);
+
+                    if self.oam_address < 4 {
+                        self.sprite_read_mode = PPUSpriteRead::Done;
+                    }
+                }
+            }
+            PPUSpriteRead::ReadRest => {
+                self.internal_oams[self.internal_oam_address] = self.curr_oam_data;
+
+                self.oam_address = self.oam_address.wrapping_add(1);
+                self.internal_oam_address += 1;
+
+                if self.internal_oam_address & 0x03 == 0 {
+                    self.sprite_count += 1;
+
+                    self.sprite_read_mode = if self.oam_address < 4 {
+                        PPUSpriteRead::Done
+                    } else if self.sprite_count == 8 {
+                        PPUSpriteRead::CheckOverflow
+                    } else {
+                        PPUSpriteRead::ReadY
+                    };
+                }
+            }
+            PPUSpriteRead::CheckOverflow => {
+                if is_in_range {
+                    self.status.set(PPUStatus::SPRITE_OVERFLOW, true);
+                    self.oam_address = self.oam_address.wrapping_add(1);
+                    self.sprite_read_mode = PPUSpriteRead::ReadOverflowRest(3);
+                } else {
+                    let n = ((self.oam_address >> 2) + 1) & 0x3f;
+                    let m = self.oam_address.wrapping_add(1) & 0x03;
+                    self.oam_address = (n << 2) | m;
+
+                    if n == 0 {
+                        self.sprite_read_mode = PPUSpriteRead::Done;
+                    }
+                }
+            }
+            PPUSpriteRead::ReadOverflowRest(remaining) => {
+                self.oam_address = self.oam_address.wrapping_add(1);
+
+                self.sprite_read_mode = if remaining > 1 {
+                    PPUSpriteRead::ReadOverflowRest(remaining - 1)
+                } else {
+                    PPUSpriteRead::Done
+                };
+            }
+            PPUSpriteRead::Done => {
+                self.oam_address = self.oam_address.wrapping_add(4);
+            }
+        }
+    }
+
+    // Read the pattern row of a sprite for the current scanline,
+    // already flipped horizontally if needed
+    fn fetch_sprite_pattern(&mut self, y: u8, id: u8, attr: u8, is_read_only: bool) -> (u8, u8) {
+        let y = y as i32;
+        let id = id as usize;
+
+        let is_sprite16_mode = self.control.contains(PPUControl::SPRITE_SIZE);
+        let pattern_sprite = if self
+            .control
+            .contains(PPUControl::SPRITE_PATTERN_TABLE_ADDRESS)
+        {
+            1
+        } else {
+            0
+        };
+
+        let sprite_pattern_address_lo: usize;
+
+        if is_sprite16_mode {
+            // 8x16 sprite mode
+            if !((attr & 0x80) > 0) {
+                // Sprite is normal, not flipped
+                if self.scanline - y < 8 {
+                    // reading top half
+                    sprite_pattern_address_lo = ((id & 0x01) << 12)
+                        | ((id & 0xfe) << 4)
+                        | ((self.scanline - y) & 0x07) as usize;
+                } else {
+                    // reading bottom half
+                    sprite_pattern_address_lo = ((id & 0x01) << 12)
+                        | (((id & 0xfe) + 1) << 4)
+                        | ((self.scanline - y) & 0x07) as usize;
+                }
+            } else {
+                // The sprite is flipped vertically
+                if self.scanline - y < 8 {
+                    // reading top half
+                    sprite_pattern_address_lo = ((id & 0x01) << 12)
+                        | (((id & 0xfe) + 1) << 4)
+                        | (7 - ((self.scanline - y) & 0x07)) as usize;
+                } else {
+                    // reading bottom half
+                    sprite_pattern_address_lo = ((id & 0x01) << 12)
+                        | ((id & 0xfe) << 4)
+                        | (7 - ((self.scanline - y) & 0x07)) as usize;
+                }
+            }
+        } else {
+            // 8x8 sprite mode
+            if !((attr & 0x80) > 0) {
+                // Sprite is normal, not flipped
+                sprite_pattern_address_lo =
+                    (pattern_sprite << 12) | (id << 4) | (self.scanline - y) as usize;
+            } else {
+                // The sprite is flipped vertically
+                sprite_pattern_address_lo =
+                    (pattern_sprite << 12) | (id << 4) | (7 - (self.scanline - y)) as usize;
+            }
+        }
+
+        let sprite_pattern_address_hi = sprite_pattern_address_lo + 8;
+
+        let mut sprite_pattern_bits_lo = self.ppu_read(sprite_pattern_address_lo, is_read_only);
+        let mut sprite_pattern_bits_hi = self.ppu_read(sprite_pattern_address_hi, is_read_only);
+
+        let is_flipped_horizontally = (attr & 0x40) > 0;
+
+        if is_flipped_horizontally {
+            sprite_pattern_bits_lo =
+                ((sprite_pattern_bits_lo & 0xf0) >> 4) | ((sprite_pattern_bits_lo & 0x0f) << 4);
+            sprite_pattern_bits_lo =
+                ((sprite_pattern_bits_lo & 0xcc) >> 2) | ((sprite_pattern_bits_lo & 0x33) << 2);
+            sprite_pattern_bits_lo =
+                ((sprite_pattern_bits_lo & 0xaa) >> 1) | ((sprite_pattern_bits_lo & 0x55) << 1);
+
+            sprite_pattern_bits_hi =
+                ((sprite_pattern_bits_hi & 0xf0) >> 4) | ((sprite_pattern_bits_hi & 0x0f) << 4);
+            sprite_pattern_bits_hi =
+                ((sprite_pattern_bits_hi & 0xcc) >> 2) | ((sprite_pattern_bits_hi & 0x33) << 2);
+            sprite_pattern_bits_hi =
+                ((sprite_pattern_bits_hi & 0xaa) >> 1) | ((sprite_pattern_bits_hi & 0x55) << 1);
+        }
+
+        (sprite_pattern_bits_lo, sprite_pattern_bits_hi)
+    }
+
+    // Display-only evaluation used when the sprite limit is disabled: every
+    // sprite on the scanline is drawn, while the real evaluation above
+    // still drives sprite 0 hit and the overflow flag
+    fn evaluate_display_sprites(&mut self, sprite_size: i32) {
+        self.display_sprite_count = 0;
+
+        if !self.no_sprite_limit || self.scanline < 0 || !self.mask.is_render_something() {
+            return;
+        }
+
+        for sprite_index in 0..64 {
+            let sprite_offset = sprite_index << 2;
+            let diff = self.scanline - (self.oams[sprite_offset] as i32);
+
+            if diff >= 0 && diff < sprite_size {
+                let (lo, hi) = self.fetch_sprite_pattern(
+                    self.oams[sprite_offset],
+                    self.oams[sprite_offset + 1],
+                    self.oams[sprite_offset + 2],
+                    true,
+                );
+                let display_offset = self.display_sprite_count << 2;
+
+                self.display_oams[display_offset..display_offset + 4]
+                    .copy_from_slice(&self.oams[sprite_offset..sprite_offset + 4]);
+                self.display_sprite_pattern_shifter[self.display_sprite_count].load_lo(lo);
+                self.display_sprite_pattern_shifter[self.display_sprite_count].load_hi(hi);
+                self.display_sprite_count += 1;
+            }
+        }
+    }
+
     fn reset_sprite_pattern_shifter(&mut self) {
         for i in 0..7 {
             self.sprite_pattern_shifter[i].load_lo(0);
@@ -574,11 +947,27 @@ impl PPU {
                     self.sprite_pattern_shifter[sprite_index].shift();
                 }
             }
+
+            for sprite_index in 0..self.display_sprite_count {
+                let index = (sprite_index << 2) + 3;
+
+                if self.display_oams[index] > 0 {
+                    self.display_oams[index] -= 1;
+                } else {
+                    self.display_sprite_pattern_shifter[sprite_index].shift();
+                }
+            }
         }
     }
 
     pub fn clock(&mut self) {
-        if self.scanline == 0 && self.cycle == 0 && self.mask.is_render_bg() {
+        self.update_nmi_output();
+
+        if self.scanline == 0
+            && self.cycle == 0
+            && self.mask.is_render_bg()
+            && self.region.has_odd_frame_skip()
+        {
             if self.odd_cycle {
                 self.cycle = 1;
             }
@@ -597,17 +986,13 @@ impl PPU {
             self.is_sprite0_hit_being_rendered = false;
         }
 
-        if self.cycle == 1 && self.scanline == 241 {
+        if self.cycle == 1 && self.scanline == self.region.vblank_scanline() {
             if !self.supress_vblank {
                 self.status.set(PPUStatus::VBLANK, true);
-
-                if self.control.contains(PPUControl::ENABLE_NMI) {
-                    self.call_nmi = true;
-                }
             }
-        }
 
-        self.supress_vblank = false;
+            self.supress_vblank = false;
+        }
 
         // visible scanline...
         if -1 <= self.scanline && self.scanline < 240 {
@@ -693,57 +1078,12 @@ impl PPU {
                     self.internal_oams[((self.cycle as usize) - 1) >> 1] = 0xff;
                 }
                 65..=256 => {
-                    if self.oam_address_loop_counter < 256 {
+                    if self.scanline >= 0 && self.mask.is_render_something() {
                         if self.cycle & 0x01 == 1 {
                             // read OAM entry
                             self.curr_oam_data = self.oams[self.oam_address as usize];
                         } else {
-                            match self.sprite_read_mode {
-                                PPUSpriteRead::ReadY => {
-                                    assert!(self.oam_address_loop_counter & 0x03 == 0);
-
-                                    self.internal_oams[self.internal_oam_address] =
-                                        self.curr_oam_data;
-
-                                    let diff = self.scanline - (self.curr_oam_data as i32);
-
-                                    if diff >= 0 && diff < sprint_size {
-                                        if self.oam_address_loop_counter == 0 {
-                                            self.is_sprite0_hit_possible = true;
-                                        }
-
-                                        self.sprite_read_mode = PPUSpriteRead::ReadRest;
-                                        self.oam_address = self.oam_address.wrapping_add(1);
-                                        self.oam_address_loop_counter += 1;
-                                        self.internal_oam_address += 1;
-                                    } else {
-                                        self.oam_address = self.oam_address.wrapping_add(4);
-                                        self.oam_address_loop_counter += 4;
-                                    }
-                                }
-                                PPUSpriteRead::ReadRest => {
-                                    self.internal_oams[self.internal_oam_address] =
-                                        self.curr_oam_data;
-
-                                    self.oam_address = self.oam_address.wrapping_add(1);
-                                    self.oam_address_loop_counter += 1;
-
-                                    self.internal_oam_address += 1;
-
-                                    if self.internal_oam_address == 32 {
-                                        self.sprite_read_mode = PPUSpriteRead::OnSpriteOverflow;
-                                    } else if self.internal_oam_address & 0x03 == 0 {
-                                        self.sprite_read_mode = PPUSpriteRead::ReadY;
-                                        self.sprite_count += 1;
-                                        // println!("SPRINT COUNT: {}", self.sprite_count);
-                                    }
-                                }
-                                PPUSpriteRead::OnSpriteOverflow => {
-                                    if self.mask.is_render_something() {
-                                        self.status.set(PPUStatus::SPRITE_OVERFLOW, true);
-                                    }
-                                }
-                            }
+                            self.evaluate_sprite(sprint_size);
                         }
                     }
                 }
@@ -757,95 +1097,20 @@ impl PPU {
 
                     self.next_scanline_sprite_count = self.sprite_count;
 
-                    let is_sprite16_mode = self.control.contains(PPUControl::SPRITE_SIZE);
-                    let pattern_sprite = if self
-                        .control
-                        .contains(PPUControl::SPRITE_PATTERN_TABLE_ADDRESS)
-                    {
-                        1
-                    } else {
-                        0
-                    };
-
                     for sprite_index in 0..self.next_scanline_sprite_count {
                         let sprite_offset = sprite_index << 2;
-                        let id = self.next_scanline_oams[sprite_offset + 1] as usize;
-                        let attr = self.next_scanline_oams[sprite_offset + 2];
-                        let y = self.next_scanline_oams[sprite_offset + 0] as i32;
-                        let sprite_pattern_address_lo: usize;
-
-                        if is_sprite16_mode {
-                            // 8x16 sprite mode
-                            if !((attr & 0x80) > 0) {
-                                // Sprite is normal, not flipped
-                                if self.scanline - y < 8 {
-                                    // reading top half
-                                    sprite_pattern_address_lo = ((id & 0x01) << 12)
-                                        | ((id & 0xfe) << 4)
-                                        | ((self.scanline - y) & 0x07) as usize;
-                                } else {
-                                    // reading bottom half
-                                    sprite_pattern_address_lo = ((id & 0x01) << 12)
-                                        | (((id & 0xfe) + 1) << 4)
-                                        | ((self.scanline - y) & 0x07) as usize;
-                                }
-                            } else {
-                                // The sprite is flipped vertically
-                                if self.scanline - y < 8 {
-                                    // reading top half
-                                    sprite_pattern_address_lo = ((id & 0x01) << 12)
-                                        | (((id & 0xfe) + 1) << 4)
-                                        | (7 - ((self.scanline - y) & 0x07)) as usize;
-                                } else {
-                                    // reading bottom half
-                                    sprite_pattern_address_lo = ((id & 0x01) << 12)
-                                        | ((id & 0xfe) << 4)
-                                        | (7 - ((self.scanline - y) & 0x07)) as usize;
-                                }
-                            }
-                        } else {
-                            // 8x8 sprite mode
-                            if !((attr & 0x80) > 0) {
-                                // Sprite is normal, not flipped
-                                sprite_pattern_address_lo = (pattern_sprite << 12)
-                                    | (id << 4)
-                                    | (self.scanline - y) as usize;
-                            } else {
-                                // The sprite is flipped vertically
-                                sprite_pattern_address_lo = (pattern_sprite << 12)
-                                    | (id << 4)
-                                    | (7 - (self.scanline - y)) as usize;
-                            }
-                        }
-
-                        let sprite_pattern_address_hi = sprite_pattern_address_lo + 8;
-
-                        let mut sprite_pattern_bits_lo =
-                            self.ppu_read(sprite_pattern_address_lo, false);
-                        let mut sprite_pattern_bits_hi =
-                            self.ppu_read(sprite_pattern_address_hi, false);
-
-                        let is_flipped_horizontally = (attr & 0x40) > 0;
-
-                        if is_flipped_horizontally {
-                            sprite_pattern_bits_lo = ((sprite_pattern_bits_lo & 0xf0) >> 4)
-                                | ((sprite_pattern_bits_lo & 0x0f) << 4);
-                            sprite_pattern_bits_lo = ((sprite_pattern_bits_lo & 0xcc) >> 2)
-                                | ((sprite_pattern_bits_lo & 0x33) << 2);
-                            sprite_pattern_bits_lo = ((sprite_pattern_bits_lo & 0xaa) >> 1)
-                                | ((sprite_pattern_bits_lo & 0x55) << 1);
-
-                            sprite_pattern_bits_hi = ((sprite_pattern_bits_hi & 0xf0) >> 4)
-                                | ((sprite_pattern_bits_hi & 0x0f) << 4);
-                            sprite_pattern_bits_hi = ((sprite_pattern_bits_hi & 0xcc) >> 2)
-                                | ((sprite_pattern_bits_hi & 0x33) << 2);
-                            sprite_pattern_bits_hi = ((sprite_pattern_bits_hi & 0xaa) >> 1)
-                                | ((sprite_pattern_bits_hi & 0x55) << 1);
-                        }
+                        let (lo, hi) = self.fetch_sprite_pattern(
+                            self.next_scanline_oams[sprite_offset],
+                            self.next_scanline_oams[sprite_offset + 1],
+                            self.next_scanline_oams[sprite_offset + 2],
+                            false,
+                        );
 
-                        self.sprite_pattern_shifter[sprite_index].load_lo(sprite_pattern_bits_lo);
-                        self.sprite_pattern_shifter[sprite_index].load_hi(sprite_pattern_bits_hi);
+                        self.sprite_pattern_shifter[sprite_index].load_lo(lo);
+                        self.sprite_pattern_shifter[sprite_index].load_hi(hi);
                     }
+
+                    self.evaluate_display_sprites(sprint_size);
                 }
                 258..=320 => {
                     self.oam_address = 0;
@@ -855,7 +1120,6 @@ impl PPU {
                     self.is_sprite0_hit_possible = false;
                     self.sprite_count = 0;
                     self.internal_oam_address = 0;
-                    self.oam_address_loop_counter = 0;
                     self.sprite_read_mode = PPUSpriteRead::ReadY;
                 }
                 _ => {}
@@ -886,6 +1150,10 @@ impl PPU {
             }
         }
 
+        if self.cycle == 0 && (0 <= self.scanline && self.scanline < 240) {
+            self.scanline_phases[self.scanline as usize] = self.dot_phase;
+        }
+
         if (self.cycle >= 0 && self.cycle < 256) && (0 <= self.scanline && self.scanline < 240) {
             let mut palette = 0;
             let mut pixel = 0;
@@ -907,27 +1175,61 @@ impl PPU {
                     // println!("RENDER SPRITE! {}", self.next_scanline_sprite_count);
                 }
 
-                self.is_sprite0_hit_being_rendered = false;
-
-                for sprite_index in 0..self.next_scanline_sprite_count {
-                    let sprite_offset = sprite_index << 2;
-                    let x = self.next_scanline_oams[sprite_offset + 3];
-                    let attr = self.next_scanline_oams[sprite_offset + 2];
-                    if x == 0 {
-                        fg_pixel = self.sprite_pattern_shifter[sprite_index].get(8);
-                        fg_palette = (attr & 0x03) + 0x04;
-                        fg_priority = attr & 0x20 == 0;
-
-                        if fg_pixel != 0 {
-                            if sprite_index == 0 {
-                                self.is_sprite0_hit_being_rendered = true;
-                            }
-                            break;
-                        }
+                let (pixel, attr, sprite_index) = front_sprite_pixel(
+                    &self.next_scanline_oams,
+                    &self.sprite_pattern_shifter,
+                    self.next_scanline_sprite_count,
+                );
+                self.is_sprite0_hit_being_rendered = pixel != 0 && sprite_index == 0;
+
+                let (pixel, attr) = if self.no_sprite_limit {
+                    let (pixel, attr, _) = front_sprite_pixel(
+                        &self.display_oams,
+                        &self.display_sprite_pattern_shifter,
+                        self.display_sprite_count,
+                    );
+                    (pixel, attr)
+                } else {
+                    (pixel, attr)
+                };
+
+                fg_pixel = pixel;
+                fg_palette = (attr & 0x03) + 0x04;
+                fg_priority = attr & 0x20 == 0;
+            }
+
+            let is_sprite0_pixel =
+                self.next_scanline_is_sprite0_hit_possible && self.is_sprite0_hit_being_rendered;
+
+            if bg_pixel != 0 && is_sprite0_pixel && self.mask.is_render_something() {
+                if self.mask.is_render_left() {
+                    if 9 <= self.cycle && self.cycle < 258 {
+                        self.status.set(PPUStatus::SPRITE0_HIT, true);
+                    }
+                } else {
+                    if 1 <= self.cycle && self.cycle < 258 {
+                        self.status.set(PPUStatus::SPRITE0_HIT, true);
                     }
                 }
             }
 
+            // everything below only changes the picture
+            let debug = self.render_debug;
+            let is_left_column = self.cycle < 8 && !debug.show_left_column;
+
+            if debug.hide_background
+                || (is_left_column && !self.mask.contains(PPUMask::SHOW_BG_LEFT))
+            {
+                bg_pixel = 0;
+            }
+
+            if debug.hide_sprites
+                || (debug.sprite0_only && !is_sprite0_pixel)
+                || (is_left_column && !self.mask.contains(PPUMask::SHOW_SPRITE_LEFT))
+            {
+                fg_pixel = 0;
+            }
+
             match (bg_pixel, fg_pixel) {
                 (0, 0) => {
                     // skip, use default
@@ -948,40 +1250,40 @@ impl PPU {
                         pixel = bg_pixel;
                         palette = bg_palette;
                     }
-
-                    if self.next_scanline_is_sprite0_hit_possible
-                        && self.is_sprite0_hit_being_rendered
-                    {
-                        if self.mask.is_render_something() {
-                            if self.mask.is_render_left() {
-                                if 9 <= self.cycle && self.cycle < 258 {
-                                    self.status.set(PPUStatus::SPRITE0_HIT, true);
-                                }
-                            } else {
-                                if 1 <= self.cycle && self.cycle < 258 {
-                                    self.status.set(PPUStatus::SPRITE0_HIT, true);
-                                }
-                            }
-                        }
-                    }
                 }
             }
 
-            let color = self.get_color(palette, pixel);
+            let pixel = if debug.highlight_sprite0_hit && bg_pixel != 0 && is_sprite0_pixel {
+                SPRITE0_HIT_HIGHLIGHT
+            } else {
+                self.get_pixel_value(palette, pixel)
+            };
+
+            self.frame
+                .set_pixel(self.cycle as usize, self.scanline as usize, pixel);
+        }
+
+        if self.vaddress_update_delay > 0 {
+            self.vaddress_update_delay -= 1;
 
-            self.screen
-                .set_pixel(self.cycle as usize, self.scanline as usize, color);
+            if self.vaddress_update_delay == 0 {
+                self.update_vaddress_from_temp();
+            }
         }
 
+        // every dot is 8 master clock half-cycles, the color subcarrier is 12
+        self.dot_phase = (self.dot_phase + 2) % 3;
         self.cycle += 1;
 
         if self.cycle >= 341 {
             self.cycle = 0;
             self.scanline += 1;
 
-            if self.scanline >= 261 {
+            if self.scanline >= self.region.scanlines() - 1 {
                 self.scanline = -1;
                 self.done_drawing = true;
+                self.render_screen();
+                self.frame_events = std::mem::take(&mut self.events);
             }
         }
     }
@@ -990,10 +1292,47 @@ impl PPU {
         self.cycle
     }
 
+    /// The current VRAM address (v)
+    pub fn vram_address(&self) -> usize {
+        self.vaddress.address()
+    }
+
+    /// The temporary VRAM address (t)
+    pub fn temp_vram_address(&self) -> usize {
+        self.temp_address.address()
+    }
+
     pub fn scanline(&self) -> i32 {
         self.scanline
     }
 
+    pub fn no_sprite_limit(&self) -> bool {
+        self.no_sprite_limit
+    }
+
+    /// Draw every sprite on a scanline instead of only the first 8. This
+    /// only changes the picture, sprite 0 hit and the overflow flag still
+    /// follow the hardware evaluation.
+    pub fn set_no_sprite_limit(&mut self, no_sprite_limit: bool) {
+        self.no_sprite_limit = no_sprite_limit;
+    }
+
+    pub fn render_debug(&self) -> RenderDebug {
+        self.render_debug
+    }
+
+    pub fn set_render_debug(&mut self, render_debug: RenderDebug) {
+        self.render_debug = render_debug;
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
     pub fn write_oam_address(&mut self, address: usize, value: u8) {
         self.oams[address] = value;
     }
@@ -1083,6 +1422,55 @@ impl PPU {
         &self.screen
     }
 
+    pub fn frame(&self) -> &IndexedScreen {
+        &self.frame
+    }
+
+    /// Color subcarrier phase (0-2, in units of 4 of the 12 phases) at the
+    /// first pixel of every visible scanline of the last frame
+    pub fn scanline_phases(&self) -> &[u8] {
+        &self.scanline_phases
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.render_screen();
+    }
+
+    fn render_screen(&mut self) {
+        self.palette.render(&self.frame, &mut self.screen);
+
+        if self.render_debug.sprite_boxes {
+            for index in 0..64 {
+                let sprite = self.debug_sprite_info(index);
+                // sprites are drawn one scanline below their Y coordinate
+                let top = sprite.y as usize + 1;
+                let left = sprite.x as usize;
+
+                if top >= NES_HEIGHT_SIZE {
+                    continue;
+                }
+
+                let bottom = (top + sprite.height - 1).min(NES_HEIGHT_SIZE - 1);
+                let right = (left + 7).min(NES_WIDTH_SIZE - 1);
+
+                for x in left..=right {
+                    self.screen.set_pixel(x, top, SPRITE_BOX_COLOR);
+                    self.screen.set_pixel(x, bottom, SPRITE_BOX_COLOR);
+                }
+
+                for y in top..=bottom {
+                    self.screen.set_pixel(left, y, SPRITE_BOX_COLOR);
+                    self.screen.set_pixel(right, y, SPRITE_BOX_COLOR);
+                }
+            }
+        }
+    }
+
     pub fn get_screen_buffer_pointer(&self) -> *const u8 {
         let pointer: *const u8;
         pointer = self.screen.image().as_ptr();
@@ -1090,7 +1478,68 @@ impl PPU {
         return pointer;
     }
 
+    // Finish a $2006 write. When the copy lands on a dot where the PPU
+    // increments v itself, the two values get ANDed together.
+    fn update_vaddress_from_temp(&mut self) {
+        let temp = self.temp_address.address();
+        let address = self.vaddress.address();
+
+        if self.is_rendering() && self.cycle == 257 {
+            self.vaddress.set_address(address & temp);
+        } else if self.is_rendering()
+            && self.cycle > 0
+            && self.cycle & 0x07 == 0
+            && (self.cycle <= 256 || self.cycle > 320)
+        {
+            // only the bits touched by the coarse X increment are affected,
+            // and on dot 256 those of the Y increment too
+            let mut mask = PPUADDRRESS_COARSE_X_MASK | PPUADDRRESS_NAMETABLE_X_SELECT_MASK;
+            if self.cycle == 256 {
+                mask |= PPUADDRRESS_FINE_Y_MASK
+                    | PPUADDRRESS_COARSE_Y_MASK
+                    | PPUADDRRESS_NAMETABLE_Y_SELECT_MASK;
+            }
+            self.vaddress
+                .set_address((temp & !mask) | (address & temp & mask));
+        } else {
+            self.vaddress.set_address(temp);
+        }
+    }
+
+    // The NMI line is asserted while VBL is set and NMI is enabled. It is
+    // sampled at the start of every dot, after the CPU had its chance to
+    // access the PPU, and the NMI only fires once it was seen for two
+    // samples. That way reading PPUSTATUS right after VBL is set, or
+    // disabling NMI, still cancels it, and enabling NMI during VBL
+    // fires one.
+    fn update_nmi_output(&mut self) {
+        let nmi_output = self.status.contains(PPUStatus::VBLANK)
+            && self.control.contains(PPUControl::ENABLE_NMI);
+
+        if nmi_output && self.prev_nmi_output && !self.nmi_triggered {
+            self.call_nmi = true;
+            self.nmi_triggered = true;
+        }
+
+        if !nmi_output {
+            self.nmi_triggered = false;
+        }
+
+        self.prev_nmi_output = nmi_output;
+    }
+
+    fn is_rendering(&self) -> bool {
+        self.scanline < 240 && self.mask.is_render_something()
+    }
+
     fn increase_vaddress(&mut self) {
+        // while rendering, $2007 accesses clock the scroll counters instead
+        if self.is_rendering() {
+            self.vaddress.increase_coarse_x();
+            self.vaddress.increase_coarse_y();
+            return;
+        }
+
         let factor = if self
             .control
             .contains(PPUControl::VRAM_ADDRESS_INCREMENT_MODE)
@@ -1104,8 +1553,19 @@ impl PPU {
 
     pub fn get_color(&mut self, palette: usize, index: usize) -> PPUColor {
         let address = 0x3f00 + (palette << 2) + index;
-        let index = self.ppu_read(address, true) as usize;
-        PPU_COLORS[index & 0x3f]
+        let index = self.ppu_read(address, true) as u16;
+        self.palette.color(index & 0x3f)
+    }
+
+    pub fn get_pixel_value(&mut self, palette: usize, index: usize) -> u16 {
+        let address = 0x3f00 + (palette << 2) + index;
+        let mut index = (self.ppu_read(address, true) & 0x3f) as u16;
+
+        if self.mask.is_greyscale() {
+            index &= 0x30;
+        }
+
+        index | self.mask.emphasis()
     }
 
     pub fn debug_nametable(&mut self, base: usize) -> Vec<String> {
@@ -1208,4 +1668,269 @@ impl PPU {
             }
         }
     }
+
+    /// Describe the tile at (x, y) of the nametable viewer, where the four
+    /// nametables are laid out as a 512x480 image
+    pub fn debug_nametable_tile_info(&mut self, x: usize, y: usize) -> NametableTileInfo {
+        let nametable = ((y / 240) << 1) | (x / 256);
+        let tile_x = (x % 256) / 8;
+        let tile_y = (y % 240) / 8;
+
+        let base_address = 0x2000 + (nametable * 0x0400);
+        let address = base_address + tile_y * 32 + tile_x;
+        let attribute_address = base_address + 0x03c0 + (tile_y / 4) * 8 + tile_x / 4;
+
+        let attribute = self.ppu_read(attribute_address, true);
+        let shift = ((tile_y & 0x02) << 1) | (tile_x & 0x02);
+
+        NametableTileInfo {
+            nametable,
+            tile_x,
+            tile_y,
+            tile_id: self.ppu_read(address, true),
+            address: address as u16,
+            attribute_address: attribute_address as u16,
+            attribute,
+            palette: (attribute >> shift) & 0x03,
+        }
+    }
+
+    /// Render the four nametables to `screen_debug_nametable` with the
+    /// current background pattern table, and draw the scroll viewport
+    pub fn set_debug_nametable_screen(&mut self) {
+        let pattern_base = if self.control.contains(PPUControl::BG_PATTERN_TABLE_ADDRESS) {
+            0x1000
+        } else {
+            0
+        };
+
+        let mut colors = Vec::new();
+        for palette in 0..8 {
+            for index in 0..4 {
+                // color 0 of every palette is the backdrop on screen
+                let palette = if index == 0 { 0 } else { palette };
+                colors.push(self.get_color(palette, index));
+            }
+        }
+
+        for tile_y in 0..60 {
+            for tile_x in 0..64 {
+                let info = self.debug_nametable_tile_info(tile_x * 8, tile_y * 8);
+                let tile_address = pattern_base + ((info.tile_id as usize) << 4);
+
+                for row in 0..8 {
+                    let mut lsb = self.ppu_read(tile_address + row, true);
+                    let mut msb = self.ppu_read(tile_address + row + 8, true);
+
+                    for col in 0..8 {
+                        let pixel_id = ((msb & 1) << 1) | (lsb & 0x01);
+                        lsb >>= 1;
+                        msb >>= 1;
+
+                        let color = colors[((info.palette as usize) << 2) | pixel_id as usize];
+                        self.screen_debug_nametable.set_pixel(
+                            tile_x * 8 + (7 - col),
+                            tile_y * 8 + row,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+
+        let scroll_x = (self.temp_address.nametable_select_x() << 8)
+            | (self.temp_address.coarse_x() << 3)
+            | self.fine_x;
+        let scroll_y = self.temp_address.nametable_select_y() * 240
+            + (self.temp_address.coarse_y() << 3)
+            + self.temp_address.fine_y();
+
+        for offset in 0..NES_WIDTH_SIZE {
+            let x = (scroll_x + offset) % 512;
+            self.screen_debug_nametable
+                .set_pixel(x, scroll_y % 480, SCROLL_OVERLAY_COLOR);
+            self.screen_debug_nametable.set_pixel(
+                x,
+                (scroll_y + NES_HEIGHT_SIZE - 1) % 480,
+                SCROLL_OVERLAY_COLOR,
+            );
+        }
+
+        for offset in 0..NES_HEIGHT_SIZE {
+            let y = (scroll_y + offset) % 480;
+            self.screen_debug_nametable
+                .set_pixel(scroll_x % 512, y, SCROLL_OVERLAY_COLOR);
+            self.screen_debug_nametable.set_pixel(
+                (scroll_x + NES_WIDTH_SIZE - 1) % 512,
+                y,
+                SCROLL_OVERLAY_COLOR,
+            );
+        }
+    }
+
+    pub fn debug_sprite_info(&self, index: usize) -> SpriteInfo {
+        let offset = index << 2;
+        let tile = self.oams[offset + 1];
+        let attr = self.oams[offset + 2];
+
+        let (pattern_address, height) = if self.control.contains(PPUControl::SPRITE_SIZE) {
+            (
+                (((tile & 0x01) as u16) << 12) | (((tile & 0xfe) as u16) << 4),
+                16,
+            )
+        } else if self
+            .control
+            .contains(PPUControl::SPRITE_PATTERN_TABLE_ADDRESS)
+        {
+            (0x1000 | ((tile as u16) << 4), 8)
+        } else {
+            ((tile as u16) << 4, 8)
+        };
+
+        SpriteInfo {
+            index,
+            x: self.oams[offset + 3],
+            y: self.oams[offset],
+            tile,
+            palette: attr & 0x03,
+            behind_background: attr & 0x20 > 0,
+            flip_horizontal: attr & 0x40 > 0,
+            flip_vertical: attr & 0x80 > 0,
+            pattern_address,
+            height,
+        }
+    }
+
+    /// Render all 64 sprites to `screen_debug_sprites` as they appear on
+    /// screen, in a grid of 8x8 cells of 8x16 pixels
+    pub fn set_debug_sprites_screen(&mut self) {
+        let backdrop = self.get_color(0, 0);
+
+        for index in 0..64 {
+            let sprite = self.debug_sprite_info(index);
+            let cell_x = (index & 0x07) * 8;
+            let cell_y = (index >> 3) * 16;
+
+            for row in 0..16 {
+                if row >= sprite.height {
+                    for col in 0..8 {
+                        self.screen_debug_sprites
+                            .set_pixel(cell_x + col, cell_y + row, backdrop);
+                    }
+                    continue;
+                }
+
+                let source_row = if sprite.flip_vertical {
+                    sprite.height - 1 - row
+                } else {
+                    row
+                };
+                let address = sprite.pattern_address as usize
+                    + ((source_row & 0x08) << 1)
+                    + (source_row & 0x07);
+                let lsb = self.ppu_read(address, true);
+                let msb = self.ppu_read(address + 8, true);
+
+                for col in 0..8 {
+                    let bit = if sprite.flip_horizontal { col } else { 7 - col };
+                    let pixel_id = (((msb >> bit) & 0x01) << 1) | ((lsb >> bit) & 0x01);
+
+                    let color = if pixel_id == 0 {
+                        backdrop
+                    } else {
+                        self.get_color(4 + sprite.palette as usize, pixel_id as usize)
+                    };
+
+                    self.screen_debug_sprites
+                        .set_pixel(cell_x + col, cell_y + row, color);
+                }
+            }
+        }
+    }
+
+    /// Read palette RAM entry `index` (0-31) without side effects
+    pub fn debug_read_palette(&mut self, index: usize) -> u8 {
+        *self.map_ppu_address(0x3f00 | (index & 0x1f)) & 0x3f
+    }
+
+    /// Write palette RAM entry `index` (0-31) like a $2007 write would,
+    /// so the $3F10/$3F14/$3F18/$3F1C mirrors are honoured
+    pub fn debug_write_palette(&mut self, index: usize, value: u8) {
+        *self.map_ppu_address(0x3f00 | (index & 0x1f)) = value & 0x3f;
+    }
+
+    pub fn event_recording(&self) -> bool {
+        self.event_recording
+    }
+
+    pub fn set_event_recording(&mut self, event_recording: bool) {
+        self.event_recording = event_recording;
+        self.events.clear();
+        self.frame_events.clear();
+    }
+
+    /// Record an event, if event recording is enabled
+    pub fn record_event(&mut self, event: PPUEvent) {
+        if self.event_recording {
+            self.events.push(event);
+        }
+    }
+
+    /// The events recorded during the last complete frame
+    pub fn last_frame_events(&self) -> &[PPUEvent] {
+        &self.frame_events
+    }
+
+    // Row of a scanline in the event viewer, the pre-render line is last
+    fn event_row(&self, scanline: i32) -> usize {
+        if scanline < 0 {
+            (self.region.scanlines() - 1) as usize
+        } else {
+            scanline as usize
+        }
+    }
+
+    /// Draw the events of the last frame on a grid of 341 dots by
+    /// the region's scanlines in `screen_debug_events`
+    pub fn set_debug_events_screen(&mut self) {
+        let rows = self.region.scanlines() as usize;
+
+        if self.screen_debug_events.height() != rows {
+            self.screen_debug_events = Screen::new(341, rows);
+        }
+
+        for y in 0..rows {
+            for x in 0..341 {
+                let is_visible = y < NES_HEIGHT_SIZE && (1..=NES_WIDTH_SIZE).contains(&x);
+                let color = if is_visible {
+                    (48, 48, 48)
+                } else {
+                    (24, 24, 24)
+                };
+                self.screen_debug_events.set_pixel(x, y, color);
+            }
+        }
+
+        for index in 0..self.frame_events.len() {
+            let event = self.frame_events[index];
+            let y = self.event_row(event.scanline);
+            self.screen_debug_events
+                .set_pixel(event.cycle as usize, y, event.color());
+        }
+    }
+
+    /// Find the recorded event closest to (x, y) of the event viewer,
+    /// within a couple of dots
+    pub fn debug_event_at(&self, x: usize, y: usize) -> Option<PPUEvent> {
+        self.frame_events
+            .iter()
+            .map(|event| {
+                let dx = (event.cycle - x as i32).abs();
+                let dy = (self.event_row(event.scanline) as i32 - y as i32).abs();
+                (dx.max(dy), event)
+            })
+            .filter(|(distance, _)| *distance <= 2)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, event)| *event)
+    }
 }
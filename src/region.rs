@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+/// TV system timing of the console.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    /// Famiclone timing: PAL frame length with an NTSC-like CPU speed
+    Dendy,
+}
+
+impl Region {
+    /// Number of scanlines per frame, including the pre-render scanline
+    pub fn scanlines(&self) -> i32 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    /// The scanline where the vertical blank flag is set
+    pub fn vblank_scanline(&self) -> i32 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    /// Master clock cycles per CPU cycle
+    pub fn cpu_divider(&self) -> u64 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    /// Master clock cycles per PPU dot
+    pub fn ppu_divider(&self) -> u64 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    /// Only the NTSC PPU skips a dot on odd frames when rendering
+    pub fn has_odd_frame_skip(&self) -> bool {
+        *self == Region::Ntsc
+    }
+}
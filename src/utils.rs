@@ -1,7 +1,5 @@
-use crate::bus::NesMemoryMapper;
+use crate::disasm::Disassembler;
 use crate::memory::Memory;
-use crate::cpu::types::*;
-use std::fmt::Write;
 
 pub struct XORShiftRand {
     state: u64,
@@ -73,99 +71,58 @@ impl Screen {
     }
 }
 
-pub fn read_cpu_instructions(nes_memory: &mut NesMemoryMapper, start_address: usize, len: usize) -> Vec<String> {
+/// A frame of raw NES pixel values: color index in bit 0-5 and the
+/// PPUMASK emphasis bits in bit 6-8
+pub struct IndexedScreen {
+    width: usize,
+    height: usize,
+    pixels: Vec<u16>,
+}
+
+impl IndexedScreen {
+    pub fn new(width: usize, height: usize) -> IndexedScreen {
+        IndexedScreen {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, pixel: u16) {
+        let pos = self.width * y + x;
+
+        assert!(pos < self.width * self.height);
+
+        self.pixels[pos] = pixel;
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> u16 {
+        self.pixels[self.width * y + x]
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &Vec<u16> {
+        &self.pixels
+    }
+}
+
+pub fn read_cpu_instructions(memory: &mut dyn Memory, start_address: usize, len: usize) -> Vec<String> {
+    let disassembler = Disassembler::new();
     let mut instructions = Vec::new();
 
     let mut current_address = start_address;
-    let mut total = 0;
-
-    while current_address < 0xffff && total < len {
-        let mut formatted_instruction = String::new();
-        let opcode = nes_memory.read(current_address, true);
-        current_address += 1;
-
-        let (adressing_mode, opcode, _) = OPCODE_TABLE[opcode as usize];
-        write!(formatted_instruction, " {:04X}: {}", (current_address - 1), opcode.to_string().to_uppercase()).unwrap();
-
-        match adressing_mode {
-            AddressMode::Imp => {
-                // do nothing
-            }
-            AddressMode::Acc => {
-                write!(formatted_instruction, " A").unwrap();
-            }
-            AddressMode::Imm => {
-                let param = nes_memory.read(current_address, true);
-                write!(formatted_instruction, " #${:02X}", param).unwrap();
-                current_address += 1;
-            }
-            AddressMode::Zp0 => {
-                let param = nes_memory.read(current_address, true);
-                write!(formatted_instruction, " ${:02X}", param).unwrap();
-                current_address += 1;
-            }
-            AddressMode::Zpx => {
-                let param = nes_memory.read(current_address, true);
-                write!(formatted_instruction, " ${:02X},X", param).unwrap();
-                current_address += 1;
-            }
-            AddressMode::Zpy => {
-                let param = nes_memory.read(current_address, true);
-                write!(formatted_instruction, " ${:02X},Y", param).unwrap();
-                current_address += 1;
-            }
-            AddressMode::Abs => {
-                let lo = nes_memory.read(current_address, true) as u16;
-                current_address += 1;
-                let hi = nes_memory.read(current_address, true) as u16;
-                current_address += 1;
-                let param = (hi << 8) | lo;
-                write!(formatted_instruction, " ${:04X}", param).unwrap();
-            }
-            AddressMode::Abx => {
-                let lo = nes_memory.read(current_address, true) as u16;
-                current_address += 1;
-                let hi = nes_memory.read(current_address, true) as u16;
-                current_address += 1;
-                let param = (hi << 8) | lo;
-                write!(formatted_instruction, " ${:04X},X", param).unwrap();
-            }
-            AddressMode::Aby => {
-                let lo = nes_memory.read(current_address, true) as u16;
-                current_address += 1;
-                let hi = nes_memory.read(current_address, true) as u16;
-                current_address += 1;
-                let param = (hi << 8) | lo;
-                write!(formatted_instruction, " ${:04X},Y", param).unwrap();
-            }
-            AddressMode::Izx => {
-                let param = nes_memory.read(current_address, true);
-                write!(formatted_instruction, " (${:02X},X)", param).unwrap();
-                current_address += 1;
-            }
-            AddressMode::Izy => {
-                let param = nes_memory.read(current_address, true);
-                write!(formatted_instruction, " (${:02X}),Y", param).unwrap();
-                current_address += 1;
-            }
-            AddressMode::Ind => {
-                let lo = nes_memory.read(current_address, true) as u16;
-                current_address += 1;
-                let hi = nes_memory.read(current_address, true) as u16;
-                current_address += 1;
-                let param = (hi << 8) | lo;
-                write!(formatted_instruction, " (${:04X})", param).unwrap();
-            }
-            AddressMode::Rel => {
-                let offset = nes_memory.read(current_address, true) as i8;
-                current_address += 1;
-                let param = (current_address as isize) + (offset as isize);
-                write!(formatted_instruction, " ${:04X}", param).unwrap();
-            }
-        }
 
-        instructions.push(formatted_instruction);
-        total += 1;
+    while current_address < 0xffff && instructions.len() < len {
+        let instruction = disassembler.decode(memory, current_address as u16);
+        instructions.push(format!(" {:04X}: {}", current_address, instruction));
+        current_address += instruction.len();
     }
 
     instructions
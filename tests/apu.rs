@@ -0,0 +1,97 @@
+mod test_utils;
+
+#[cfg(test)]
+mod apu_tests {
+    use crate::test_utils::nrom_bus;
+    use nesrs::bus::Bus;
+    use nesrs::cpu::types::IrqSource;
+    use nesrs::memory::*;
+
+    // Run until the instruction at `pc` is about to start, and return
+    // the CPU cycle count
+    fn run_until(bus: &mut Bus, pc: u16) -> u64 {
+        bus.clock();
+        while !(bus.cpu.done() && bus.cpu.regs.pc == pc) {
+            bus.clock();
+        }
+
+        bus.cpu_total_cycles()
+    }
+
+    // SEI, write `frame_control` to $4017 and loop. The loop starts at $8006.
+    fn frame_counter_bus(frame_control: u8) -> Bus {
+        let mut program = [
+            0x78, // SEI
+            0xa9, 0x00, // LDA #frame_control
+            0x8d, 0x17, 0x40, // STA $4017
+            0x4c, 0x06, 0x80, // JMP $8006
+        ];
+        program[2] = frame_control;
+
+        nrom_bus(&program)
+    }
+
+    #[test]
+    fn it_sets_the_frame_irq_in_four_step_mode() {
+        let mut bus = frame_counter_bus(0x00);
+        let start = run_until(&mut bus, 0x8006);
+
+        while !bus.irq_sources().contains(IrqSource::APU_FRAME) {
+            bus.clock();
+        }
+        assert_eq!(bus.cpu_total_cycles() - start, 29830);
+
+        // reading $4015 reports the flag and clears it
+        assert_eq!(bus.memory().read(0x4015, true), 0x40);
+        assert_eq!(bus.memory().read(0x4015, false), 0x40);
+        assert_eq!(bus.memory().read(0x4015, false), 0x00);
+    }
+
+    #[test]
+    fn it_only_sets_the_frame_irq_when_enabled() {
+        // IRQ inhibit, then 5-step mode
+        for &frame_control in [0x40, 0x80].iter() {
+            let mut bus = frame_counter_bus(frame_control);
+            let start = run_until(&mut bus, 0x8006);
+
+            while bus.cpu_total_cycles() < start + 2 * 37282 {
+                bus.clock();
+                assert!(!bus.irq_sources().contains(IrqSource::APU_FRAME));
+            }
+        }
+    }
+
+    #[test]
+    fn it_fetches_dmc_samples_through_dma() {
+        let program = [
+            0x78, // SEI
+            0xa9, 0x8f, // LDA #$8F
+            0x8d, 0x10, 0x40, // STA $4010
+            0xa9, 0x00, // LDA #$00
+            0x8d, 0x12, 0x40, // STA $4012
+            0xa9, 0x01, // LDA #$01
+            0x8d, 0x13, 0x40, // STA $4013
+            0xa9, 0x10, // LDA #$10
+            0x8d, 0x15, 0x40, // STA $4015
+            0x4c, 0x15, 0x80, // JMP $8015
+        ];
+        // $C000, 17 bytes, with an IRQ at the end
+        let mut bus = nrom_bus(&program);
+        let start = run_until(&mut bus, 0x8015);
+
+        // the first of the 17 bytes is fetched right away, the others
+        // each time an output cycle of 8 bits at rate $F ends. When the
+        // first one ends depends on the timer at power on.
+        while !bus.irq_sources().contains(IrqSource::APU_DMC) {
+            assert_eq!(bus.memory().read(0x4015, true), 0x10);
+            bus.clock();
+        }
+        let cycles = bus.cpu_total_cycles() - start;
+        assert!((16 * 8 * 54..17 * 8 * 54).contains(&cycles), "{}", cycles);
+
+        assert_eq!(bus.memory().read(0x4015, false), 0x80);
+        // disabling the DMC acknowledges its IRQ
+        bus.memory().write(0x4015, 0x00);
+        assert_eq!(bus.memory().read(0x4015, false), 0x00);
+    }
+}
@@ -0,0 +1,246 @@
+mod test_utils;
+
+#[cfg(test)]
+mod blargg_tests {
+    use crate::test_utils::nrom_bus;
+    use nesrs::bus::*;
+    use nesrs::memory::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // blargg's test ROMs, as collected in https://github.com/christopherpow/nes-test-roms.
+    // Put the suite directories in rom/blargg, or point BLARGG_TESTS_DIR
+    // to them, and run the ignored tests with `cargo test -- --ignored`.
+    // A missing ROM fails its test.
+    const DEFAULT_DIR: &str = "rom/blargg";
+
+    // how long a ROM gets to report its result
+    const MAX_FRAMES: usize = 60 * 60;
+
+    fn load(rom: &str) -> Result<Bus, String> {
+        let dir = PathBuf::from(
+            std::env::var("BLARGG_TESTS_DIR").unwrap_or_else(|_| DEFAULT_DIR.to_string()),
+        );
+        let path = dir.join(rom);
+
+        if !path.is_file() {
+            return Err(format!("{} not found", path.display()));
+        }
+
+        let bytes = fs::read(&path).unwrap();
+        let mut bus = Bus::new_from_array(&bytes).unwrap();
+        bus.reset();
+
+        Ok(bus)
+    }
+
+    // The ROMs from 2005 only show their result on screen, and keep its
+    // code at $F8: 1 when passed, the number of the failed test otherwise
+    fn run_legacy(bus: &mut Bus) -> Result<(), String> {
+        for _ in 0..MAX_FRAMES {
+            bus.clock_until_frame_done();
+
+            match bus.memory().read(0x00f8, true) {
+                0 => {}
+                1 => return Ok(()),
+                code => return Err(format!("failed test {}", code)),
+            }
+        }
+
+        Err(format!("no result after {} frames", MAX_FRAMES))
+    }
+
+    // The later ROMs report through $6000: $80 while running, $81 when
+    // they need the reset button pressed, then the result code, 0 when
+    // passed. $6001-$6003 hold DE B0 61 once that is valid, and the text
+    // they print is kept from $6004.
+    fn run(bus: &mut Bus) -> Result<(), String> {
+        let mut reset_at = None;
+
+        for frame in 0..MAX_FRAMES {
+            bus.clock_until_frame_done();
+
+            let memory = bus.memory();
+            let signature = [
+                memory.read(0x6001, true),
+                memory.read(0x6002, true),
+                memory.read(0x6003, true),
+            ];
+            if signature != [0xde, 0xb0, 0x61] {
+                continue;
+            }
+
+            match memory.read(0x6000, true) {
+                0x80 => reset_at = None,
+                // the reset has to come at least 100 ms later
+                0x81 => match reset_at {
+                    None => reset_at = Some(frame + 10),
+                    Some(at) if at == frame => bus.reset(),
+                    _ => {}
+                },
+                0 => return Ok(()),
+                code => return Err(format!("failed with code {}: {}", code, text(bus))),
+            }
+        }
+
+        Err(format!("no result after {} frames", MAX_FRAMES))
+    }
+
+    fn text(bus: &mut Bus) -> String {
+        let memory = bus.memory();
+
+        (0x6004..0x7000)
+            .map(|address| memory.read(address, true))
+            .take_while(|&byte| byte != 0)
+            .map(|byte| byte as char)
+            .collect::<String>()
+            .trim()
+            .to_string()
+    }
+
+    fn run_suite(roms: &[&str], run: fn(&mut Bus) -> Result<(), String>) {
+        let mut failures = vec![];
+
+        for rom in roms.iter() {
+            if let Err(error) = load(rom).and_then(|mut bus| run(&mut bus)) {
+                failures.push(format!("{}: {}", rom, error));
+            }
+        }
+
+        for failure in failures.iter() {
+            println!("{}", failure);
+        }
+
+        assert!(
+            failures.is_empty(),
+            "{} of {} ROMs failed",
+            failures.len(),
+            roms.len()
+        );
+    }
+
+    #[test]
+    fn it_reads_legacy_results() {
+        // LDA #$01, STA $F8, JMP *
+        let mut bus = nrom_bus(&[0xa9, 0x01, 0x85, 0xf8, 0x4c, 0x04, 0x80]);
+        assert_eq!(run_legacy(&mut bus), Ok(()));
+
+        let mut bus = nrom_bus(&[0xa9, 0x03, 0x85, 0xf8, 0x4c, 0x04, 0x80]);
+        assert_eq!(run_legacy(&mut bus), Err("failed test 3".to_string()));
+    }
+
+    #[test]
+    fn it_fails_on_missing_roms() {
+        let error = load("missing/missing.nes").err().unwrap();
+        assert!(error.ends_with("missing.nes not found"), "{}", error);
+    }
+
+    #[test]
+    fn it_reads_results_at_6000() {
+        // DE B0 61 at $6001, "Hi" at $6004, then `code` at $6000
+        let program = |code: u8| {
+            let mut program = vec![];
+            for (address, value) in [
+                (0x01, 0xde),
+                (0x02, 0xb0),
+                (0x03, 0x61),
+                (0x04, b'H'),
+                (0x05, b'i'),
+                (0x06, 0x00),
+                (0x00, code),
+            ] {
+                // LDA #value, STA $60xx
+                program.extend_from_slice(&[0xa9, value, 0x8d, address, 0x60]);
+            }
+            // JMP *
+            program.extend_from_slice(&[0x4c, 0x23, 0x80]);
+            program
+        };
+
+        assert_eq!(run(&mut nrom_bus(&program(0x00))), Ok(()));
+        assert_eq!(
+            run(&mut nrom_bus(&program(0x02))),
+            Err("failed with code 2: Hi".to_string())
+        );
+    }
+
+    #[test]
+    #[ignore = "needs the ROMs in rom/blargg or BLARGG_TESTS_DIR"]
+    fn it_passes_sprite_overflow_tests() {
+        run_suite(
+            &[
+                "sprite_overflow_tests/1.Basics.nes",
+                "sprite_overflow_tests/2.Details.nes",
+                "sprite_overflow_tests/3.Timing.nes",
+                "sprite_overflow_tests/4.Obscure.nes",
+                "sprite_overflow_tests/5.Emulator.nes",
+            ],
+            run_legacy,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs the ROMs in rom/blargg or BLARGG_TESTS_DIR"]
+    fn it_passes_ppu_vbl_nmi() {
+        run_suite(
+            &[
+                "ppu_vbl_nmi/rom_singles/01-vbl_basics.nes",
+                "ppu_vbl_nmi/rom_singles/02-vbl_set_time.nes",
+                "ppu_vbl_nmi/rom_singles/03-vbl_clear_time.nes",
+                "ppu_vbl_nmi/rom_singles/04-nmi_control.nes",
+                "ppu_vbl_nmi/rom_singles/05-nmi_timing.nes",
+                "ppu_vbl_nmi/rom_singles/06-suppression.nes",
+                "ppu_vbl_nmi/rom_singles/07-nmi_on_timing.nes",
+                "ppu_vbl_nmi/rom_singles/08-nmi_off_timing.nes",
+                "ppu_vbl_nmi/rom_singles/09-even_odd_frames.nes",
+                "ppu_vbl_nmi/rom_singles/10-even_odd_timing.nes",
+            ],
+            run,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs the ROMs in rom/blargg or BLARGG_TESTS_DIR"]
+    fn it_passes_vbl_nmi_timing() {
+        run_suite(
+            &[
+                "vbl_nmi_timing/1.frame_basics.nes",
+                "vbl_nmi_timing/2.vbl_timing.nes",
+                "vbl_nmi_timing/3.even_odd_frames.nes",
+                "vbl_nmi_timing/4.vbl_clear_timing.nes",
+                "vbl_nmi_timing/5.nmi_suppression.nes",
+                "vbl_nmi_timing/6.nmi_disable.nes",
+                "vbl_nmi_timing/7.nmi_timing.nes",
+            ],
+            run_legacy,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs the ROMs in rom/blargg or BLARGG_TESTS_DIR"]
+    fn it_passes_cpu_interrupts_v2() {
+        run_suite(
+            &[
+                "cpu_interrupts_v2/rom_singles/1-cli_latency.nes",
+                "cpu_interrupts_v2/rom_singles/2-nmi_and_brk.nes",
+                "cpu_interrupts_v2/rom_singles/3-nmi_and_irq.nes",
+                "cpu_interrupts_v2/rom_singles/4-irq_and_dma.nes",
+                "cpu_interrupts_v2/rom_singles/5-branch_delays_irq.nes",
+            ],
+            run,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs the ROMs in rom/blargg or BLARGG_TESTS_DIR"]
+    fn it_passes_dma_tests() {
+        run_suite(
+            &[
+                "sprdma_and_dmc_dma/sprdma_and_dmc_dma.nes",
+                "sprdma_and_dmc_dma/sprdma_and_dmc_dma_512.nes",
+                "dmc_dma_during_read4/dma_2007_read.nes",
+            ],
+            run,
+        );
+    }
+}
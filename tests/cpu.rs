@@ -7,6 +7,7 @@ mod macros;
 #[cfg(test)]
 mod cpu_tests {
     use crate::test_utils::RAM;
+    use nesrs::cpu::trace::*;
     use nesrs::cpu::types::*;
     use nesrs::cpu::*;
     use nesrs::memory::*;
@@ -53,7 +54,7 @@ mod cpu_tests {
         let prev_cycle = cpu.total_cycles;
 
         cpu.regs.p |= StatusFlag::I;
-        cpu.irq();
+        cpu.set_irq(IrqSource::EXTERNAL, true);
 
         loop_cpu!(cpu, memory);
         cpu.print_debug();
@@ -61,10 +62,14 @@ mod cpu_tests {
         assert_ne!(cpu.regs.pc, 0x6000);
         assert_ne!(cpu.total_cycles - prev_cycle, 7);
 
-        let prev_cycle = cpu.total_cycles;
         cpu.regs.p &= !StatusFlag::I;
-        cpu.irq();
+        cpu.set_irq(IrqSource::EXTERNAL, true);
+
+        // the IRQ is seen while the next instruction runs
+        loop_cpu!(cpu, memory);
+        cpu.print_debug();
 
+        let prev_cycle = cpu.total_cycles;
         let pc = cpu.regs.pc;
         loop_cpu!(cpu, memory);
         cpu.print_debug();
@@ -102,11 +107,13 @@ mod cpu_tests {
         loop_cpu!(cpu, memory);
         cpu.print_debug();
 
-        let prev_cycle = cpu.total_cycles;
-
         cpu.regs.p |= StatusFlag::I;
         cpu.nmi();
 
+        loop_cpu!(cpu, memory);
+        cpu.print_debug();
+
+        let prev_cycle = cpu.total_cycles;
         let pc = cpu.regs.pc;
         loop_cpu!(cpu, memory);
         cpu.print_debug();
@@ -121,4 +128,679 @@ mod cpu_tests {
         assert_eq!(cpu.regs.pc, pc);
         assert_eq!(cpu.total_cycles - prev_cycle, 6);
     }
+
+    fn interrupt_test_cpu() -> (CPU, RAM) {
+        let mut cpu = CPU::new();
+        let mut memory = RAM::new();
+
+        set_reset!(memory, 0x8000);
+
+        memory.write(0xfffe, 0x00);
+        memory.write(0xffff, 0x60);
+
+        memory.write(0xfffa, 0x00);
+        memory.write(0xfffb, 0xc0);
+
+        cpu.reset();
+        loop_cpu!(cpu, memory);
+
+        (cpu, memory)
+    }
+
+    #[test]
+    fn it_delays_irq_by_one_instruction_after_cli() {
+        let (mut cpu, mut memory) = interrupt_test_cpu();
+
+        // CLI, NOP
+        set_ram!(memory, 0x8000, [0x58, 0xea]);
+        cpu.set_irq(IrqSource::EXTERNAL, true);
+
+        loop_cpu!(cpu, memory);
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.pc, 0x8002);
+
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.pc, 0x6000);
+    }
+
+    #[test]
+    fn it_takes_irq_right_after_sei() {
+        let (mut cpu, mut memory) = interrupt_test_cpu();
+
+        // CLI, NOP, SEI
+        set_ram!(memory, 0x8000, [0x58, 0xea, 0x78]);
+
+        loop_cpu!(cpu, memory);
+        loop_cpu!(cpu, memory);
+        cpu.set_irq(IrqSource::EXTERNAL, true);
+
+        loop_cpu!(cpu, memory);
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.pc, 0x6000);
+
+        // the pushed status has I set, but not B
+        let status = memory.read(0x0100 + cpu.regs.sp as usize + 1, true);
+        assert_eq!(status & 0x14, 0x04);
+    }
+
+    #[test]
+    fn it_delays_irq_on_taken_branch() {
+        let (mut cpu, mut memory) = interrupt_test_cpu();
+
+        // CLI, BNE +0, NOP
+        set_ram!(memory, 0x8000, [0x58, 0xd0, 0x00, 0xea]);
+        loop_cpu!(cpu, memory);
+
+        // IRQ arrives while the branch offset is read
+        cpu.clock(&mut memory);
+        cpu.set_irq(IrqSource::EXTERNAL, true);
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.pc, 0x8003);
+
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.pc, 0x8004);
+
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.pc, 0x6000);
+    }
+
+    #[test]
+    fn it_hijacks_brk_with_nmi() {
+        let (mut cpu, mut memory) = interrupt_test_cpu();
+
+        // BRK
+        memory.write(0x8000, 0x00);
+
+        cpu.clock(&mut memory);
+        cpu.clock(&mut memory);
+        cpu.nmi();
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.pc, 0xc000);
+
+        // it's still a BRK on the stack
+        let status = memory.read(0x0100 + cpu.regs.sp as usize + 1, true);
+        assert_eq!(status & 0x10, 0x10);
+
+        // and the NMI isn't taken twice
+        loop_cpu!(cpu, memory);
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.pc, 0xc002);
+    }
+
+    #[test]
+    fn it_keeps_irq_asserted_until_acknowledged() {
+        let (mut cpu, mut memory) = interrupt_test_cpu();
+
+        // NOP, CLI, NOP, NOP
+        set_ram!(memory, 0x8000, [0xea, 0x58, 0xea, 0xea]);
+
+        cpu.set_irq(IrqSource::MAPPER, true);
+        cpu.set_irq(IrqSource::APU_FRAME, true);
+
+        // I is set, so the IRQ waits for CLI instead of being dropped
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.pc, 0x8001);
+
+        cpu.set_irq(IrqSource::MAPPER, false);
+        assert_eq!(cpu.irq_sources(), IrqSource::APU_FRAME);
+
+        loop_cpu!(cpu, memory);
+        loop_cpu!(cpu, memory);
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.pc, 0x6000);
+    }
+
+    #[test]
+    fn it_ignores_irq_acknowledged_before_cli() {
+        let (mut cpu, mut memory) = interrupt_test_cpu();
+
+        // NOP, CLI, NOP, NOP
+        set_ram!(memory, 0x8000, [0xea, 0x58, 0xea, 0xea]);
+
+        cpu.set_irq(IrqSource::EXTERNAL, true);
+        loop_cpu!(cpu, memory);
+        cpu.set_irq(IrqSource::EXTERNAL, false);
+
+        loop_cpu!(cpu, memory);
+        loop_cpu!(cpu, memory);
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.pc, 0x8004);
+    }
+
+    // every bus access as (address, value, is write)
+    struct LoggedRAM {
+        ram: RAM,
+        log: Vec<(usize, u8, bool)>,
+    }
+
+    impl Memory for LoggedRAM {
+        fn read(&mut self, address: usize, is_read_only: bool) -> u8 {
+            let value = self.ram.read(address, true);
+            if !is_read_only {
+                self.log.push((address, value, false));
+            }
+            value
+        }
+
+        fn write(&mut self, address: usize, value: u8) {
+            self.log.push((address, value, true));
+            self.ram.write(address, value);
+        }
+    }
+
+    // run one instruction at `start` and log the bus during it
+    fn log_instruction(
+        start: u16,
+        program: &[u8],
+        setup: impl FnOnce(&mut CPU, &mut RAM),
+    ) -> (CPU, Vec<(usize, u8, bool)>) {
+        let mut cpu = CPU::new();
+        let mut memory = LoggedRAM {
+            ram: RAM::new(),
+            log: vec![],
+        };
+
+        set_reset!(memory, start);
+        for (offset, byte) in program.iter().enumerate() {
+            memory.ram.write(start as usize + offset, *byte);
+        }
+
+        cpu.reset();
+        loop_cpu!(cpu, memory);
+
+        setup(&mut cpu, &mut memory.ram);
+        memory.log.clear();
+
+        let cycles = cpu.total_cycles;
+        loop_cpu!(cpu, memory);
+        assert_eq!((cpu.total_cycles - cycles) as usize, memory.log.len());
+
+        (cpu, memory.log)
+    }
+
+    #[test]
+    fn it_does_dummy_reads_on_indexed_addressing() {
+        // LDA $10F0,X crossing a page
+        let (cpu, log) = log_instruction(0x8000, &[0xbd, 0xf0, 0x10], |cpu, _| {
+            cpu.regs.x = 0x20;
+        });
+        assert_eq!(
+            log,
+            vec![
+                (0x8000, 0xbd, false),
+                (0x8001, 0xf0, false),
+                (0x8002, 0x10, false),
+                (0x1010, 0xea, false),
+                (0x1110, 0xea, false),
+            ]
+        );
+        assert_eq!(cpu.regs.a, 0xea);
+
+        // STA $1000,X always reads before the write
+        let (_, log) = log_instruction(0x8000, &[0x9d, 0x00, 0x10], |cpu, _| {
+            cpu.regs.x = 0x01;
+            cpu.regs.a = 0x42;
+        });
+        assert_eq!(
+            log,
+            vec![
+                (0x8000, 0x9d, false),
+                (0x8001, 0x00, false),
+                (0x8002, 0x10, false),
+                (0x1001, 0xea, false),
+                (0x1001, 0x42, true),
+            ]
+        );
+
+        // LDA $F0,X reads the unindexed address first
+        let (_, log) = log_instruction(0x8000, &[0xb5, 0xf0], |cpu, _| {
+            cpu.regs.x = 0x20;
+        });
+        assert_eq!(
+            log,
+            vec![
+                (0x8000, 0xb5, false),
+                (0x8001, 0xf0, false),
+                (0x00f0, 0xea, false),
+                (0x0010, 0xea, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_writes_the_unmodified_value_on_read_modify_write() {
+        // INC $10
+        let (_, log) = log_instruction(0x8000, &[0xe6, 0x10], |_, memory| {
+            memory.write(0x10, 0x7f);
+        });
+        assert_eq!(
+            log,
+            vec![
+                (0x8000, 0xe6, false),
+                (0x8001, 0x10, false),
+                (0x0010, 0x7f, false),
+                (0x0010, 0x7f, true),
+                (0x0010, 0x80, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_does_dummy_reads_on_stack_instructions() {
+        // JSR $1234
+        let (cpu, log) = log_instruction(0x8000, &[0x20, 0x34, 0x12], |_, _| {});
+        assert_eq!(
+            log,
+            vec![
+                (0x8000, 0x20, false),
+                (0x8001, 0x34, false),
+                (0x01fd, 0xea, false),
+                (0x01fd, 0x80, true),
+                (0x01fc, 0x02, true),
+                (0x8002, 0x12, false),
+            ]
+        );
+        assert_eq!(cpu.regs.pc, 0x1234);
+
+        // RTS
+        let (cpu, log) = log_instruction(0x8000, &[0x60], |cpu, memory| {
+            cpu.regs.sp = 0xfb;
+            memory.write(0x01fc, 0x02);
+            memory.write(0x01fd, 0x80);
+        });
+        assert_eq!(
+            log,
+            vec![
+                (0x8000, 0x60, false),
+                (0x8001, 0xea, false),
+                (0x01fb, 0xea, false),
+                (0x01fc, 0x02, false),
+                (0x01fd, 0x80, false),
+                (0x8002, 0xea, false),
+            ]
+        );
+        assert_eq!(cpu.regs.pc, 0x8003);
+
+        // PLA
+        let (_, log) = log_instruction(0x8000, &[0x68], |cpu, memory| {
+            cpu.regs.sp = 0xfc;
+            memory.write(0x01fd, 0x42);
+        });
+        assert_eq!(
+            log,
+            vec![
+                (0x8000, 0x68, false),
+                (0x8001, 0xea, false),
+                (0x01fc, 0xea, false),
+                (0x01fd, 0x42, false),
+            ]
+        );
+
+        // BRK
+        let (cpu, log) = log_instruction(0x8000, &[0x00], |_, _| {});
+        assert_eq!(
+            log,
+            vec![
+                (0x8000, 0x00, false),
+                (0x8001, 0xea, false),
+                (0x01fd, 0x80, true),
+                (0x01fc, 0x02, true),
+                (0x01fb, 0x34, true),
+                (0xfffe, 0xea, false),
+                (0xffff, 0xea, false),
+            ]
+        );
+        assert_eq!(cpu.regs.pc, 0xeaea);
+    }
+
+    #[test]
+    fn it_does_dummy_reads_on_taken_branches() {
+        // BNE +$10 from $80FD lands on the next page
+        let (cpu, log) = log_instruction(0x80fd, &[0xd0, 0x10], |_, _| {});
+        assert_eq!(
+            log,
+            vec![
+                (0x80fd, 0xd0, false),
+                (0x80fe, 0x10, false),
+                (0x80ff, 0xea, false),
+                (0x800f, 0xea, false),
+            ]
+        );
+        assert_eq!(cpu.regs.pc, 0x810f);
+    }
+
+    #[test]
+    fn it_traces_bus_cycles() {
+        let mut cpu = CPU::new();
+        let mut memory = RAM::new();
+        set_reset!(memory, 0x8000);
+
+        // STA $10
+        set_ram!(memory, 0x8000, [0x85, 0x10]);
+
+        cpu.reset();
+        loop_cpu!(cpu, memory);
+        assert!(cpu.trace().is_none());
+
+        cpu.regs.a = 0x42;
+        cpu.enable_trace(2);
+        loop_cpu!(cpu, memory);
+
+        let trace = cpu.disable_trace().unwrap();
+        assert_eq!(
+            trace.cycles().copied().collect::<Vec<_>>(),
+            vec![
+                BusCycle {
+                    cycle: 8,
+                    address: 0x8001,
+                    value: 0x10,
+                    is_read: true,
+                },
+                BusCycle {
+                    cycle: 9,
+                    address: 0x0010,
+                    value: 0x42,
+                    is_read: false,
+                },
+            ]
+        );
+        assert_eq!(trace.to_string(), "8 8001 10 R\n9 0010 42 W\n");
+        assert!(cpu.trace().is_none());
+    }
+
+    fn variant_test_cpu(variant: CpuVariant, program: &[u8]) -> (CPU, RAM) {
+        let mut cpu = CPU::new_with_variant(variant);
+        let mut memory = RAM::new();
+        set_reset!(memory, 0x8000);
+
+        for (offset, value) in program.iter().enumerate() {
+            memory.write(0x8000 + offset, *value);
+        }
+
+        cpu.reset();
+        loop_cpu!(cpu, memory);
+
+        (cpu, memory)
+    }
+
+    #[test]
+    fn it_ignores_decimal_mode_on_2a03() {
+        // SED, CLC, LDA #$19, ADC #$28
+        let program = [0xf8, 0x18, 0xa9, 0x19, 0x69, 0x28];
+        let (mut cpu, mut memory) = variant_test_cpu(CpuVariant::Ricoh2A03, &program);
+
+        for _ in 0..4 {
+            loop_cpu!(cpu, memory);
+        }
+
+        assert_eq!(cpu.regs.a, 0x41);
+        assert!(cpu.regs.p.contains(StatusFlag::D));
+    }
+
+    #[test]
+    fn it_does_bcd_arithmetic_on_nmos() {
+        // SED, CLC, LDA #$19, ADC #$28, ADC #$53, SEC, SBC #$01, SBC #$01
+        let program = [
+            0xf8, 0x18, 0xa9, 0x19, 0x69, 0x28, 0x69, 0x53, 0x38, 0xe9, 0x01, 0xe9, 0x01,
+        ];
+        let (mut cpu, mut memory) = variant_test_cpu(CpuVariant::Nmos6502, &program);
+
+        for _ in 0..4 {
+            loop_cpu!(cpu, memory);
+        }
+        assert_eq!(cpu.regs.a, 0x47);
+        assert!(!cpu.regs.p.contains(StatusFlag::C));
+
+        // 47 + 53 = 100
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.a, 0x00);
+        assert!(cpu.regs.p.contains(StatusFlag::C));
+        // Z comes from the binary sum on NMOS
+        assert!(!cpu.regs.p.contains(StatusFlag::Z));
+
+        loop_cpu!(cpu, memory);
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.a, 0x99);
+        assert!(!cpu.regs.p.contains(StatusFlag::C));
+
+        // 99 - 01 - borrow = 97
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.a, 0x97);
+        assert!(cpu.regs.p.contains(StatusFlag::C));
+    }
+
+    #[test]
+    fn it_takes_an_extra_cycle_on_decimal_adc_on_65c02() {
+        // SED, CLC, LDA #$99, ADC #$01
+        let program = [0xf8, 0x18, 0xa9, 0x99, 0x69, 0x01];
+        let (mut cpu, mut memory) = variant_test_cpu(CpuVariant::Wdc65C02, &program);
+
+        for _ in 0..3 {
+            loop_cpu!(cpu, memory);
+        }
+
+        let cycles = cpu.total_cycles;
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.total_cycles - cycles, 3);
+        assert_eq!(cpu.regs.a, 0x00);
+        assert!(cpu.regs.p.contains(StatusFlag::C));
+        assert!(cpu.regs.p.contains(StatusFlag::Z));
+    }
+
+    #[test]
+    fn it_runs_65c02_opcodes() {
+        let program = [
+            0x80, 0x02, // BRA +2
+            0xea, 0xea, // skipped
+            0xa2, 0x42, // LDX #$42
+            0xda, // PHX
+            0xa2, 0x00, // LDX #$00
+            0xfa, // PLX
+            0x64, 0x10, // STZ $10
+            0xa9, 0x0f, // LDA #$0F
+            0x04, 0x10, // TSB $10
+            0xa9, 0x03, // LDA #$03
+            0x14, 0x10, // TRB $10
+            0xf7, 0x10, // SMB7 $10
+            0x07, 0x10, // RMB0 $10
+            0x1a, // INC A
+            0x92, 0x20, // STA ($20)
+            0x9f, 0x10, 0x02, // BBS1 $10, +2
+            0xea, 0xea, // skipped
+            0x6c, 0xff, 0x02, // JMP ($02FF)
+        ];
+        let (mut cpu, mut memory) = variant_test_cpu(CpuVariant::Wdc65C02, &program);
+        memory.write(0x10, 0xff);
+        memory.write(0x20, 0x00);
+        memory.write(0x21, 0x03);
+        memory.write(0x02ff, 0x34);
+        memory.write(0x0300, 0x12);
+        memory.write(0x0200, 0x56);
+
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.pc, 0x8004);
+
+        for _ in 0..4 {
+            loop_cpu!(cpu, memory);
+        }
+        assert_eq!(cpu.regs.x, 0x42);
+        assert_eq!(cpu.regs.sp, 0xfd);
+
+        loop_cpu!(cpu, memory);
+        assert_eq!(memory.read(0x10, true), 0x00);
+
+        loop_cpu!(cpu, memory);
+        loop_cpu!(cpu, memory);
+        assert_eq!(memory.read(0x10, true), 0x0f);
+        assert!(cpu.regs.p.contains(StatusFlag::Z));
+
+        loop_cpu!(cpu, memory);
+        loop_cpu!(cpu, memory);
+        assert_eq!(memory.read(0x10, true), 0x0c);
+        assert!(!cpu.regs.p.contains(StatusFlag::Z));
+
+        loop_cpu!(cpu, memory);
+        assert_eq!(memory.read(0x10, true), 0x8c);
+
+        // RMB0 on a clear bit
+        loop_cpu!(cpu, memory);
+        assert_eq!(memory.read(0x10, true), 0x8c);
+
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.a, 0x04);
+
+        loop_cpu!(cpu, memory);
+        assert_eq!(memory.read(0x0300, true), 0x04);
+        memory.write(0x0300, 0x12);
+
+        // bit 1 of $8C is clear, so BBS1 doesn't branch
+        let cycles = cpu.total_cycles;
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.total_cycles - cycles, 5);
+        assert_eq!(cpu.regs.pc, 0x801e);
+
+        loop_cpu!(cpu, memory);
+        loop_cpu!(cpu, memory);
+        let cycles = cpu.total_cycles;
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.total_cycles - cycles, 6);
+        assert_eq!(cpu.regs.pc, 0x1234);
+    }
+
+    #[test]
+    fn it_takes_the_65c02_nop_cycles() {
+        let program = [
+            0x5c, 0x34, 0x12, // NOP $1234, 8 cycles
+            0xdc, 0x34, 0x12, // NOP $1234, 4 cycles
+            0x03, // NOP, 1 cycle
+            0x02, 0x44, // NOP #$44, 2 cycles
+        ];
+        let (mut cpu, mut memory) = variant_test_cpu(CpuVariant::Wdc65C02, &program);
+
+        cpu.enable_trace(8);
+        let cycles = cpu.total_cycles;
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.total_cycles - cycles, 8);
+        assert_eq!(
+            cpu.trace()
+                .unwrap()
+                .cycles()
+                .map(|cycle| cycle.address)
+                .collect::<Vec<_>>(),
+            vec![0x8000, 0x8001, 0x8002, 0xff34, 0xffff, 0xffff, 0xffff, 0xffff]
+        );
+
+        for expected in [4, 1, 2] {
+            let cycles = cpu.total_cycles;
+            loop_cpu!(cpu, memory);
+            assert_eq!(cpu.total_cycles - cycles, expected);
+        }
+        assert_eq!(cpu.regs.pc, 0x8009);
+    }
+
+    #[test]
+    fn it_keeps_the_jmp_indirect_page_wrap_on_nmos() {
+        // JMP ($02FF)
+        let program = [0x6c, 0xff, 0x02];
+        let (mut cpu, mut memory) = variant_test_cpu(CpuVariant::Nmos6502, &program);
+        memory.write(0x02ff, 0x34);
+        memory.write(0x0300, 0x12);
+        memory.write(0x0200, 0x56);
+
+        loop_cpu!(cpu, memory);
+        assert_eq!(cpu.regs.pc, 0x5634);
+    }
+
+    #[test]
+    fn it_predicts_the_next_read() {
+        let program = [
+            0x58, // CLI
+            0xa2, 0x02, // LDX #$02
+            0xbd, 0xff, 0x02, // LDA $02FF,X
+            0x99, 0x00, 0x02, // STA $0200,Y
+            0xe6, 0x10, // INC $10
+            0x06, 0x10, // ASL $10
+            0xa1, 0x20, // LDA ($20,X)
+            0x91, 0x20, // STA ($20),Y
+            0x48, // PHA
+            0x68, // PLA
+            0x20, 0x80, 0x80, // JSR $8080
+            0x00, 0xea, // BRK
+            0xf8, // SED
+            0x18, // CLC
+            0xa9, 0x19, // LDA #$19
+            0x69, 0x28, // ADC #$28
+            0x38, // SEC
+            0xe9, 0x09, // SBC #$09
+            0xd8, // CLD
+            0x18, // CLC
+            0x90, 0x00, // BCC +0
+            0x4c, 0xf0, 0x80, // JMP $80F0
+        ];
+        // the 65C02 only opcodes at $8200
+        let cmos_program = [
+            0x07, 0x10, // RMB0 $10
+            0x87, 0x10, // SMB0 $10
+            0x14, 0x10, // TRB $10
+            0x04, 0x10, // TSB $10
+            0x0f, 0x10, 0x00, // BBR0 $10,+0
+            0x8f, 0x10, 0x00, // BBS0 $10,+0
+            0x5c, 0x34, 0x12, // NOP $1234, reads $FF34
+            0xcb, // WAI
+            0xdb, // STP
+        ];
+
+        for variant in [
+            CpuVariant::Ricoh2A03,
+            CpuVariant::Nmos6502,
+            CpuVariant::Wdc65C02,
+        ] {
+            let (mut cpu, mut memory) = variant_test_cpu(variant, &program);
+            // RTS
+            memory.write(0x8080, 0x60);
+            // BCC +$10 to $8102, BCS, JMP ($0300)
+            set_ram!(memory, 0x80f0, [0x90, 0x10]);
+            set_ram!(memory, 0x8102, [0xb0, 0xfe, 0x6c, 0x00, 0x03]);
+            set_ram!(memory, 0x0300, [0x00, 0x82]);
+            if variant.is_cmos() {
+                for (offset, value) in cmos_program.iter().enumerate() {
+                    memory.write(0x8200 + offset, *value);
+                }
+            } else {
+                // JMP $8200
+                set_ram!(memory, 0x8200, [0x4c, 0x00, 0x82]);
+            }
+            // NMI, BRK and IRQ return right away
+            memory.write(0x9000, 0x40);
+            set_ram!(memory, 0xfffa, [0x00, 0x90]);
+            set_ram!(memory, 0xfffe, [0x00, 0x90]);
+            cpu.enable_trace(1);
+
+            for i in 0..1200 {
+                // IRQs wake up WAI, a reset gets out of STP
+                cpu.set_irq(IrqSource::EXTERNAL, i % 150 >= 140);
+                if i % 150 == 75 {
+                    cpu.nmi();
+                }
+                if i == 900 {
+                    cpu.reset();
+                }
+
+                let expected = cpu.next_read_address();
+                let pc = cpu.regs.pc as usize;
+                let total_cycles = cpu.total_cycles;
+                cpu.clock(&mut memory);
+
+                let cycle = *cpu.trace().unwrap().cycles().next().unwrap();
+                if cycle.cycle != total_cycles {
+                    // no bus access
+                    assert_eq!(expected, Some(pc), "{:?} cycle {}", variant, i);
+                } else if cycle.is_read {
+                    assert_eq!(expected, Some(cycle.address as usize), "{}", cycle);
+                } else {
+                    assert_eq!(expected, None, "{}", cycle);
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,149 @@
+mod test_utils;
+
+#[cfg(test)]
+mod disasm_tests {
+    use crate::test_utils::RAM;
+    use nesrs::cpu::types::*;
+    use nesrs::disasm::symbols::*;
+    use nesrs::disasm::*;
+    use nesrs::memory::*;
+    use nesrs::utils::read_cpu_instructions;
+
+    #[test]
+    fn it_decodes_instructions() {
+        let disassembler = Disassembler::new();
+        // LDA $0300,X / BNE -7 / JSR $C000
+        let bytes = [0xbd, 0x00, 0x03, 0xd0, 0xf9, 0x20, 0x00, 0xc0];
+
+        let lda = disassembler.decode_bytes(&bytes, 0x8000, 0x8000);
+        assert_eq!(
+            lda,
+            Instruction {
+                address: 0x8000,
+                bytes: vec![0xbd, 0x00, 0x03],
+                opcode: Opcode::Lda,
+                mode: AddressMode::Abx,
+                operand: Some(0x0300),
+                target: None,
+            }
+        );
+        assert_eq!(lda.to_string(), "LDA $0300,X");
+
+        let bne = disassembler.decode_bytes(&bytes, 0x8000, 0x8003);
+        assert_eq!(bne.target, Some(0x7ffe));
+        assert_eq!(bne.to_string(), "BNE $7FFE");
+
+        let jsr = disassembler.decode_bytes(&bytes, 0x8000, 0x8005);
+        assert_eq!(jsr.target, Some(0xc000));
+
+        // the same from memory
+        let mut memory = RAM::new();
+        for (offset, value) in bytes.iter().enumerate() {
+            memory.write(0x8000 + offset, *value);
+        }
+        let instructions = disassembler.disassemble(&mut memory, 0x8000, 3);
+        assert_eq!(instructions, vec![lda, bne, jsr]);
+
+        assert_eq!(
+            read_cpu_instructions(&mut memory, 0x8000, 4),
+            vec![
+                " 8000: LDA $0300,X",
+                " 8003: BNE $7FFE",
+                " 8005: JSR $C000",
+                " 8008: NOP"
+            ]
+        );
+    }
+
+    #[test]
+    fn it_decodes_65c02_instructions() {
+        let disassembler = Disassembler::new_with_variant(CpuVariant::Wdc65C02);
+        // BBS3 $12, +2 / STA ($20) / JMP ($1234,X)
+        let bytes = [0xbf, 0x12, 0x02, 0x92, 0x20, 0x7c, 0x34, 0x12];
+
+        let bbs = disassembler.decode_bytes(&bytes, 0x8000, 0x8000);
+        assert_eq!(bbs.operand, Some(0x12));
+        assert_eq!(bbs.target, Some(0x8005));
+        assert_eq!(bbs.to_string(), "BBS3 $12,$8005");
+
+        let sta = disassembler.decode_bytes(&bytes, 0x8000, 0x8003);
+        assert_eq!(sta.to_string(), "STA ($20)");
+
+        let jmp = disassembler.decode_bytes(&bytes, 0x8000, 0x8005);
+        assert_eq!(jmp.target, None);
+        assert_eq!(jmp.to_string(), "JMP ($1234,X)");
+    }
+
+    #[test]
+    fn it_loads_labels() {
+        let mut labels = Labels::new();
+        labels
+            .load_nl("$C000#Reset#entry point\n$0300/10#Buffer#\n$0010##only a comment\n")
+            .unwrap();
+        assert_eq!(labels.get(0xc000), Some("Reset"));
+        assert_eq!(labels.get(0x0300), Some("Buffer"));
+        assert_eq!(labels.get(0x0010), None);
+        assert!(labels.load_nl("C0X0#Bad#\n").is_err());
+
+        let mut labels = Labels::new();
+        labels
+            .load_mlb(
+                "P:0010:Nmi:vblank\nR:0020-002F:Scratch\nS:0000:Save\nG:2000:PpuCtrl\n",
+                0x8000,
+            )
+            .unwrap();
+        assert_eq!(labels.get(0x8010), Some("Nmi"));
+        assert_eq!(labels.get(0x0020), Some("Scratch"));
+        assert_eq!(labels.get(0x6000), Some("Save"));
+        assert_eq!(labels.get(0x2000), Some("PpuCtrl"));
+        assert_eq!(labels.len(), 4);
+
+        let mut labels = Labels::new();
+        labels
+            .load_dbg(concat!(
+                "version\tmajor=2,minor=0\n",
+                "sym\tid=0,name=\"reset\",addrsize=absolute,scope=0,def=3,val=0x8000,seg=0,type=lab\n",
+                "sym\tid=1,name=\"SPEED\",addrsize=zeropage,scope=0,def=1,val=4,type=equ\n",
+                "sym\tid=2,name=\"counter\",addrsize=zeropage,scope=0,def=2,val=16,type=lab\n",
+            ))
+            .unwrap();
+        assert_eq!(labels.get(0x8000), Some("reset"));
+        assert_eq!(labels.get(0x0010), Some("counter"));
+        assert_eq!(labels.get(0x0004), None);
+    }
+
+    #[test]
+    fn it_lists_a_bank_with_labels_and_code_data_log() {
+        let mut disassembler = Disassembler::new();
+        disassembler.labels.insert(0xc000, "reset");
+        disassembler.labels.insert(0xc006, "table");
+        disassembler.labels.insert(0x0300, "buffer");
+
+        // LDA $0300 / JMP $C000 / table data
+        let bank = [0xad, 0x00, 0x03, 0x4c, 0x00, 0xc0, 0x01, 0x02];
+        let mut cdl = [CDL_CODE; 8];
+        cdl[6] = CDL_DATA;
+        cdl[7] = CDL_DATA;
+
+        assert_eq!(
+            disassembler.listing(&bank, 0xc000, Some(&cdl)),
+            concat!(
+                "reset:\n",
+                "  C000  AD 00 03  LDA buffer\n",
+                "  C003  4C 00 C0  JMP reset\n",
+                "table:\n",
+                "  C006  .byte $01, $02\n",
+            )
+        );
+
+        // without a log it's all code, and the cut off JMP becomes data
+        assert_eq!(
+            disassembler.listing(&bank[..5], 0xc000, None),
+            concat!(
+                "reset:\n",
+                "  C000  AD 00 03  LDA buffer\n",
+                "  C003  .byte $4C, $00\n",
+            )
+        );
+    }
+}
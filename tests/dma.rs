@@ -0,0 +1,140 @@
+mod test_utils;
+
+#[cfg(test)]
+mod dma_tests {
+    use crate::test_utils::nrom_bus;
+    use nesrs::bus::Bus;
+    use nesrs::controller::ButtonStatus;
+    use nesrs::memory::*;
+
+    // Run until the instruction at `pc` is about to start, and return
+    // the CPU cycle count
+    fn run_until(bus: &mut Bus, pc: u16) -> u64 {
+        bus.clock();
+        while !(bus.cpu.done() && bus.cpu.regs.pc == pc) {
+            bus.clock();
+        }
+
+        bus.cpu_total_cycles()
+    }
+
+    // The CPU cycle the OAM DMA of the STA $4014 at `sta` halts the CPU
+    // on, and the cycles it was halted for. The NOP that follows is left
+    // out. A DMC DMA can be requested `dmc_delay` CPU cycles into the OAM
+    // DMA.
+    fn oam_dma_halt(program: &[u8], sta: u16, dmc_delay: Option<u64>) -> (u64, u64) {
+        let mut bus = nrom_bus(program);
+        for i in 0..0x100 {
+            bus.memory().write(0x0200 + i, i as u8);
+        }
+
+        let start = run_until(&mut bus, sta);
+        if let Some(delay) = dmc_delay {
+            while bus.cpu_total_cycles() < start + 4 + delay {
+                bus.clock();
+            }
+            bus.request_dmc_dma(0xc000);
+        }
+        let end = run_until(&mut bus, sta + 4);
+
+        let ppu = bus.ppu.lock().unwrap();
+        for i in 0..0x100 {
+            assert_eq!(ppu.oams[i], i as u8);
+        }
+
+        // STA $4014 and NOP
+        (start + 4, end - start - 4 - 2)
+    }
+
+    #[test]
+    fn it_aligns_oam_dma_to_get_cycles() {
+        let mut program = vec![
+            0x2c, 0x02, 0x20, // BIT $2002
+            0x10, 0xfb, // BPL $8000
+            0xa9, 0x00, // LDA #$00
+            0x8d, 0x03, 0x20, // STA $2003
+            0xa9, 0x02, // LDA #$02
+            0x8d, 0x14, 0x40, // STA $4014
+            0xea, // NOP
+        ];
+        // halted on a get cycle, the DMA waits for the next one
+        let (cycle, halt) = oam_dma_halt(&program, 0x800c, None);
+        assert_eq!(cycle % 2, 1);
+        assert_eq!(halt, 514);
+
+        // LDX $00 moves the DMA by one cycle
+        program.splice(5..5, [0xa6, 0x00].iter().copied());
+        let (cycle, halt) = oam_dma_halt(&program, 0x800e, None);
+        assert_eq!(cycle % 2, 0);
+        assert_eq!(halt, 513);
+
+        // a DMC DMA in the middle takes one get cycle and one to realign
+        for delay in 100..102 {
+            assert_eq!(oam_dma_halt(&program, 0x800e, Some(delay)).1, 513 + 2);
+        }
+    }
+
+    // The CPU cycle a DMC DMA requested before the instruction at `pc`
+    // halts the CPU on, and the cycles it was halted for. The instruction
+    // takes `cycles`.
+    fn dmc_dma_halt(bus: &mut Bus, pc: u16, cycles: u64) -> (u64, u64) {
+        let start = run_until(bus, pc);
+        bus.request_dmc_dma(0xc000);
+        let end = run_until(bus, pc + 1);
+
+        // $C000 mirrors the program
+        assert_eq!(bus.take_dmc_sample(), Some(0xea));
+        assert_eq!(bus.take_dmc_sample(), None);
+
+        (start, end - start - cycles)
+    }
+
+    #[test]
+    fn it_fetches_dmc_samples() {
+        // NOP, LDX $00, NOP
+        let program = [0xea, 0xa6, 0x00, 0xea];
+
+        // halted on a get cycle: the halt, a dummy read and the fetch
+        let mut bus = nrom_bus(&program);
+        let (cycle, halt) = dmc_dma_halt(&mut bus, 0x8000, 2);
+        assert_eq!(cycle % 2, 1);
+        assert_eq!(halt, 3);
+
+        // on a put cycle, the fetch waits one more cycle
+        let mut bus = nrom_bus(&program);
+        run_until(&mut bus, 0x8001);
+        let (cycle, halt) = dmc_dma_halt(&mut bus, 0x8003, 2);
+        assert_eq!(cycle % 2, 0);
+        assert_eq!(halt, 4);
+    }
+
+    #[test]
+    fn it_repeats_the_halted_read() {
+        let program = [
+            0xa9, 0x01, // LDA #$01
+            0x8d, 0x16, 0x40, // STA $4016
+            0xa9, 0x00, // LDA #$00
+            0x8d, 0x16, 0x40, // STA $4016
+            0xad, 0x16, 0x40, // LDA $4016
+            0xea, // NOP
+        ];
+        let mut bus = nrom_bus(&program);
+        bus.press_controller_button(0, ButtonStatus::A, true);
+
+        // without DMA, LDA reads A
+        run_until(&mut bus, 0x800d);
+        assert_eq!(bus.cpu.regs.a & 1, 1);
+
+        // the DMC DMA halts the CPU on the $4016 read, so LDA gets B
+        let mut bus = nrom_bus(&program);
+        bus.press_controller_button(0, ButtonStatus::A, true);
+        run_until(&mut bus, 0x800a);
+        while bus.cpu.next_read_address() != Some(0x4016) {
+            bus.clock();
+        }
+        bus.request_dmc_dma(0xc000);
+
+        run_until(&mut bus, 0x800d);
+        assert_eq!(bus.cpu.regs.a & 1, 0);
+    }
+}
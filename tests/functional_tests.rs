@@ -0,0 +1,189 @@
+mod test_utils;
+
+#[cfg(test)]
+mod functional_tests {
+    use crate::test_utils::RAM;
+    use nesrs::cpu::types::*;
+    use nesrs::cpu::*;
+    use nesrs::disasm::Disassembler;
+    use std::collections::VecDeque;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // Klaus Dormann's 6502 tests, https://github.com/Klaus2m5/6502_65C02_functional_tests.
+    // `make bin` in rom/test assembles them with ca65 into rom/test/bin, or
+    // point FUNCTIONAL_TESTS_DIR to other builds, and run the ignored tests
+    // with `cargo test -- --ignored`. Both tests end in a self-loop.
+    const DEFAULT_DIR: &str = "rom/test/bin";
+
+    // how many instructions get dumped when a test fails
+    const HISTORY: usize = 32;
+
+    // The image loaded at `load_address` in zeroed memory
+    fn load(image: &[u8], load_address: usize) -> RAM {
+        let mut memory = RAM {
+            ram: vec![0; 0x10000],
+        };
+        let end = (load_address + image.len()).min(memory.ram.len());
+        memory.ram[load_address..end].copy_from_slice(&image[..end - load_address]);
+
+        memory
+    }
+
+    struct Runner {
+        cpu: CPU,
+        memory: RAM,
+        // start address of the last instructions
+        history: VecDeque<u16>,
+    }
+
+    impl Runner {
+        fn new(variant: CpuVariant, memory: RAM) -> Self {
+            Runner {
+                cpu: CPU::new_with_variant(variant),
+                memory,
+                history: VecDeque::with_capacity(HISTORY),
+            }
+        }
+
+        // Run from `start` until an instruction jumps to itself, and
+        // return the address of that instruction
+        fn run_until_trap(&mut self, start: u16, max_instructions: usize) -> Result<u16, String> {
+            self.cpu.set_registers(CPURegisters {
+                a: 0,
+                x: 0,
+                y: 0,
+                sp: 0xfd,
+                p: StatusFlag::I | StatusFlag::U,
+                pc: start,
+            });
+            self.history.clear();
+
+            for _ in 0..max_instructions {
+                self.cpu.clock(&mut self.memory);
+                while !self.cpu.done() {
+                    self.cpu.clock(&mut self.memory);
+                }
+
+                if self.history.len() == HISTORY {
+                    self.history.pop_front();
+                }
+                self.history.push_back(self.cpu.instruction_pc());
+
+                if self.cpu.regs.pc == self.cpu.instruction_pc() {
+                    return Ok(self.cpu.regs.pc);
+                }
+            }
+
+            Err(format!(
+                "no trap after {} instructions, PC at ${:04X}",
+                max_instructions, self.cpu.regs.pc
+            ))
+        }
+
+        fn failure_report(&mut self, error: &str) -> String {
+            let disassembler = Disassembler::new_with_variant(self.cpu.variant());
+            let mut report = format!("{}\nlast instructions:\n", error);
+
+            for &address in self.history.iter() {
+                let instruction = disassembler.decode(&mut self.memory, address);
+                let bytes = instruction
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                report.push_str(&format!(
+                    "  {:04X}  {:8}  {}\n",
+                    address, bytes, instruction
+                ));
+            }
+
+            report
+        }
+    }
+
+    fn read_image(name: &str) -> Vec<u8> {
+        let path = PathBuf::from(
+            std::env::var("FUNCTIONAL_TESTS_DIR").unwrap_or_else(|_| DEFAULT_DIR.to_string()),
+        )
+        .join(name);
+
+        fs::read(&path).unwrap_or_else(|_| panic!("{} not found", path.display()))
+    }
+
+    #[test]
+    fn it_reports_the_trap_address() {
+        // LDX #$03, DEX, BNE -3, JMP *
+        let program = [0xa2, 0x03, 0xca, 0xd0, 0xfd, 0x4c, 0x05, 0x04];
+        let mut runner = Runner::new(CpuVariant::Ricoh2A03, load(&program, 0x0400));
+
+        assert_eq!(runner.run_until_trap(0x0400, 100), Ok(0x0405));
+
+        // a branch to itself is a trap too
+        // LDA #$00, BEQ -2
+        let program = [0xa9, 0x00, 0xf0, 0xfe];
+        let mut runner = Runner::new(CpuVariant::Ricoh2A03, load(&program, 0x0400));
+
+        assert_eq!(runner.run_until_trap(0x0400, 100), Ok(0x0402));
+
+        // the failure dump ends with the instructions up to the trap
+        let report = runner.failure_report("failed");
+        assert_eq!(
+            report,
+            concat!(
+                "failed\n",
+                "last instructions:\n",
+                "  0400  A9 00     LDA #$00\n",
+                "  0402  F0 FE     BEQ $0402\n",
+            )
+        );
+    }
+
+    #[test]
+    #[ignore = "needs the binaries from `make bin` in rom/test or FUNCTIONAL_TESTS_DIR"]
+    fn it_passes_the_functional_test() {
+        // an address to trap at on success, otherwise any JMP * once the
+        // test_case at $0200 reads $F0
+        let success = std::env::var("FUNCTIONAL_TEST_SUCCESS")
+            .ok()
+            .map(|address| u16::from_str_radix(address.trim_start_matches('$'), 16).unwrap());
+
+        let image = read_image("6502_functional_test.bin");
+        let mut runner = Runner::new(CpuVariant::Nmos6502, load(&image, 0x0000));
+
+        match runner.run_until_trap(0x0400, 100_000_000) {
+            Ok(trap) if success == Some(trap) => {}
+            Ok(trap)
+                if success.is_none()
+                    && runner.memory.ram[trap as usize] == 0x4c
+                    && runner.memory.ram[0x0200] == 0xf0 => {}
+            Ok(trap) => panic!(
+                "{}",
+                runner.failure_report(&format!(
+                    "trapped at ${:04X} in test ${:02X}",
+                    trap, runner.memory.ram[0x0200]
+                ))
+            ),
+            Err(error) => panic!("{}", runner.failure_report(&error)),
+        }
+    }
+
+    #[test]
+    #[ignore = "needs the binaries from `make bin` in rom/test or FUNCTIONAL_TESTS_DIR"]
+    fn it_passes_the_decimal_test() {
+        let image = read_image("6502_decimal_test.bin");
+        let mut runner = Runner::new(CpuVariant::Nmos6502, load(&image, 0x0200));
+
+        // ERROR at $000B stays 0 when everything went well
+        match runner.run_until_trap(0x0200, 100_000_000) {
+            Ok(_) if runner.memory.ram[0x000b] == 0 => {}
+            Ok(trap) => panic!(
+                "{}",
+                runner.failure_report(&format!("trapped at ${:04X} with ERROR set", trap))
+            ),
+            Err(error) => panic!("{}", runner.failure_report(&error)),
+        }
+    }
+}
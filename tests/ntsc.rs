@@ -0,0 +1,105 @@
+#[cfg(test)]
+mod ntsc_tests {
+    use nesrs::ntsc::*;
+    use nesrs::palette::*;
+    use nesrs::utils::*;
+
+    fn pixel_at(screen: &Screen, x: usize, y: usize) -> (u8, u8, u8) {
+        let pos = (y * screen.width() + x) * 4;
+        let image = screen.image();
+        (image[pos], image[pos + 1], image[pos + 2])
+    }
+
+    #[test]
+    fn it_decodes_flat_colors_back_to_the_palette() {
+        let mut frame = IndexedScreen::new(256, 240);
+        for y in 0..240 {
+            for x in 0..256 {
+                frame.set_pixel(x, y, 0x16);
+            }
+        }
+
+        let phases: Vec<u8> = (0..240).map(|line| (line % 3) as u8).collect();
+        let expected = Palette::from_preset(PalettePreset::Ntsc2C02).color(0x16);
+        let width = NtscFilter::output_width(256);
+        assert_eq!(width, 602);
+
+        for setup in [
+            NtscSetup::composite(),
+            NtscSetup::svideo(),
+            NtscSetup::rgb(),
+        ]
+        .iter()
+        {
+            let mut screen = Screen::new(width, 240);
+            NtscFilter::new(*setup).render(&frame, &phases, &mut screen);
+
+            let (r, g, b) = pixel_at(&screen, 300, 120);
+            let close = |a: u8, b: u8| (a as i32 - b as i32).abs() <= 2;
+            assert!(close(r, expected.0) && close(g, expected.1) && close(b, expected.2));
+        }
+    }
+
+    // a red field on the left, white on the right, filtered with every
+    // scanline starting at `phase`
+    fn render_edge(setup: NtscSetup, phase: u8) -> Screen {
+        let mut frame = IndexedScreen::new(256, 240);
+        for y in 0..240 {
+            for x in 0..256 {
+                frame.set_pixel(x, y, if x < 128 { 0x16 } else { 0x30 });
+            }
+        }
+
+        let phases = vec![phase; 240];
+        let mut screen = Screen::new(NtscFilter::output_width(256), 240);
+        NtscFilter::new(setup).render(&frame, &phases, &mut screen);
+        screen
+    }
+
+    // output pixels that differ between two renders, around the edge
+    fn edge_differences(a: &Screen, b: &Screen) -> usize {
+        (290..310)
+            .filter(|&x| pixel_at(a, x, 120) != pixel_at(b, x, 120))
+            .count()
+    }
+
+    #[test]
+    fn it_crawls_with_the_subcarrier_phase() {
+        let composite = NtscSetup::composite();
+        let phase0 = render_edge(composite, 0);
+
+        assert!(edge_differences(&phase0, &render_edge(composite, 1)) > 0);
+        assert!(edge_differences(&phase0, &render_edge(composite, 2)) > 0);
+
+        // the crawl only shows at the edge
+        assert_eq!(
+            pixel_at(&phase0, 100, 120),
+            pixel_at(&render_edge(composite, 1), 100, 120)
+        );
+    }
+
+    #[test]
+    fn it_separates_luma_and_chroma_on_svideo() {
+        let composite = render_edge(NtscSetup::composite(), 0);
+        let svideo = render_edge(NtscSetup::svideo(), 0);
+
+        assert!(edge_differences(&composite, &svideo) > 0);
+    }
+
+    #[test]
+    fn it_does_not_bleed_on_rgb() {
+        let screen = render_edge(NtscSetup::rgb(), 0);
+        let red = pixel_at(&screen, 0, 120);
+        let white = pixel_at(&screen, screen.width() - 1, 120);
+
+        for x in 0..screen.width() {
+            let color = pixel_at(&screen, x, 120);
+            assert!(color == red || color == white);
+        }
+
+        assert_eq!(
+            edge_differences(&screen, &render_edge(NtscSetup::rgb(), 1)),
+            0
+        );
+    }
+}
@@ -0,0 +1,74 @@
+#[cfg(test)]
+mod palette_tests {
+    use nesrs::palette::*;
+    use nesrs::ppu::PPU_COLORS;
+
+    #[test]
+    fn it_can_load_pal_files() {
+        let base: Vec<u8> = (0..192).map(|value| value as u8).collect();
+        let palette = Palette::from_pal(&base).unwrap();
+
+        assert_eq!(palette.color(0x01), (3, 4, 5));
+        assert_ne!(palette.color(0x41), (3, 4, 5));
+
+        let full = palette.to_pal();
+        assert_eq!(full.len(), 1536);
+
+        let reloaded = Palette::from_pal(&full).unwrap();
+        for pixel in 0..0x200 {
+            assert_eq!(reloaded.color(pixel), palette.color(pixel));
+        }
+
+        assert!(Palette::from_pal(&base[0..100]).is_err());
+    }
+
+    #[test]
+    fn it_builds_every_preset() {
+        for preset in PalettePreset::ALL.iter() {
+            let palette = Palette::from_preset(*preset);
+            assert_eq!(palette.to_pal().len(), 0x200 * 3, "{}", preset.name());
+        }
+
+        let classic = Palette::from_preset(PalettePreset::Classic);
+        for (index, color) in PPU_COLORS.iter().enumerate() {
+            assert_eq!(classic.color(index as u16), *color);
+        }
+
+        let smooth = Palette::from_preset(PalettePreset::Smooth);
+        assert_eq!(smooth.color(0x00), (0x6a, 0x6d, 0x6a));
+        assert_eq!(smooth.color(0x16), (0x98, 0x37, 0x00));
+        assert_eq!(smooth.color(0x2d), (0x4a, 0x4d, 0x4a));
+        assert_eq!(smooth.color(0x30), (0xff, 0xff, 0xff));
+
+        // the 2C05 shares the 2C03 colors
+        assert_eq!(
+            Palette::from_preset(PalettePreset::Rgb2C05).to_pal(),
+            Palette::from_preset(PalettePreset::Rgb2C03).to_pal()
+        );
+    }
+
+    #[test]
+    fn it_darkens_emphasized_colors() {
+        let brightness = |(r, g, b): (u8, u8, u8)| r as u32 + g as u32 + b as u32;
+
+        for preset in [
+            PalettePreset::Classic,
+            PalettePreset::Fceux,
+            PalettePreset::Smooth,
+        ]
+        .iter()
+        {
+            let palette = Palette::from_preset(*preset);
+
+            // red emphasis keeps red and darkens green and blue
+            let (r, g, b) = palette.color(0x20);
+            let (er, eg, eb) = palette.color(0x60);
+            assert!(er == r && eg < g && eb < b);
+
+            for emphasis in 1..8 {
+                let color = 0x16 | (emphasis << 6);
+                assert!(brightness(palette.color(color)) < brightness(palette.color(0x16)));
+            }
+        }
+    }
+}
@@ -0,0 +1,557 @@
+mod test_utils;
+
+#[cfg(test)]
+mod ppu_tests {
+    use crate::test_utils::*;
+    use nesrs::bus::Bus;
+    use nesrs::cartridge::*;
+    use nesrs::memory::*;
+    use nesrs::ppu::*;
+    use nesrs::region::Region;
+    use std::sync::{Arc, Mutex};
+
+    fn cartridge(rom: &Vec<u8>) -> CartridgeRef {
+        Arc::new(Mutex::new(Cartridge::parse(rom).unwrap()))
+    }
+
+    fn nrom_cartridge() -> CartridgeRef {
+        cartridge(&nrom_rom(&[]))
+    }
+
+    // a CPU can't access the PPU more often than once every 3 dots,
+    // so give every register write a few dots to settle
+    fn write_register(ppu: &mut PPU, register: usize, value: u8) {
+        ppu.write(register, value);
+
+        for _ in 0..12 {
+            ppu.clock();
+        }
+    }
+
+    fn write_vram(ppu: &mut PPU, address: usize, value: u8) {
+        write_register(ppu, 0x06, (address >> 8) as u8);
+        write_register(ppu, 0x06, (address & 0xff) as u8);
+        write_register(ppu, 0x07, value);
+    }
+
+    fn clock_frame(ppu: &mut PPU) {
+        while !ppu.done_drawing {
+            ppu.clock();
+        }
+
+        ppu.done_drawing = false;
+    }
+
+    #[test]
+    fn it_outputs_indexed_pixels_with_emphasis_and_greyscale() {
+        let mut ppu = PPU::new(nrom_cartridge());
+
+        write_vram(&mut ppu, 0x3f00, 0x16);
+        write_register(&mut ppu, 0x01, 0x20);
+        clock_frame(&mut ppu);
+
+        assert_eq!(ppu.frame().get_pixel(10, 10), 0x56);
+
+        let color = ppu.palette().color(0x56);
+        let pos = (10 * NES_WIDTH_SIZE + 10) * 4;
+        let image = ppu.screen().image();
+        assert_eq!((image[pos], image[pos + 1], image[pos + 2]), color);
+        assert_ne!(color, ppu.palette().color(0x16));
+
+        write_register(&mut ppu, 0x01, 0x01);
+        clock_frame(&mut ppu);
+
+        assert_eq!(ppu.frame().get_pixel(10, 10), 0x10);
+    }
+
+    #[test]
+    fn it_uses_the_region_frame_length() {
+        let mut rom = nrom_rom(&[]);
+        rom[7] = 0x08;
+        rom[12] = 0x03;
+        assert_eq!(Cartridge::parse(&rom).unwrap().region(), Region::Dendy);
+
+        let mut ppu = PPU::new(nrom_cartridge());
+        assert_eq!(ppu.region(), Region::Ntsc);
+
+        for (region, dots) in [(Region::Pal, 341 * 312), (Region::Dendy, 341 * 312)] {
+            ppu.set_region(region);
+            clock_frame(&mut ppu);
+
+            let mut count = 0;
+            while !ppu.done_drawing {
+                ppu.clock();
+                count += 1;
+            }
+            ppu.done_drawing = false;
+
+            assert_eq!(count, dots);
+        }
+    }
+
+    #[test]
+    fn it_derives_every_clock_from_the_master_clock() {
+        for (region, ppu_divider, cpu_divider) in [
+            (Region::Ntsc, 4, 12),
+            (Region::Pal, 5, 16),
+            (Region::Dendy, 5, 15),
+        ] {
+            // JMP $8000
+            let mut bus = nrom_bus(&[0x4c, 0x00, 0x80]);
+            bus.set_region(region);
+
+            for _ in 0..1000 {
+                bus.clock();
+            }
+
+            assert_eq!(bus.master_clock(), 1000 * ppu_divider);
+            assert_eq!(bus.ppu_total_cycles(), 1000);
+
+            // the CPU runs on the first dot, then once per divider
+            let cpu_cycles = (999 * ppu_divider) / cpu_divider + 1;
+            assert_eq!(bus.cpu_total_cycles(), cpu_cycles);
+            assert_eq!(bus.cpu.total_cycles, cpu_cycles);
+        }
+
+        // the trace counts the cycles the CPU is halted by DMA
+        let mut bus = nrom_bus(&[
+            0xa9, 0x02, // LDA #$02
+            0x8d, 0x14, 0x40, // STA $4014
+            0x4c, 0x05, 0x80, // JMP $8005
+        ]);
+        bus.cpu.enable_trace(1000);
+        for _ in 0..3000 {
+            bus.clock();
+        }
+
+        let cycles = bus
+            .cpu
+            .trace()
+            .unwrap()
+            .cycles()
+            .map(|cycle| cycle.cycle)
+            .collect::<Vec<_>>();
+        let longest_gap = cycles.windows(2).map(|pair| pair[1] - pair[0]).max();
+        assert!(longest_gap >= Some(514));
+        assert_eq!(
+            cycles.last().map(|cycle| cycle + 1),
+            Some(bus.cpu_total_cycles())
+        );
+        assert_eq!(bus.cpu.total_cycles, bus.cpu_total_cycles());
+    }
+
+    fn sprite_overflow_after_frame(sprites: &[(u8, u8)]) -> bool {
+        let mut ppu = PPU::new(nrom_cartridge());
+
+        for (index, (y, tile)) in sprites.iter().enumerate() {
+            ppu.write_oam_address(index * 4, *y);
+            ppu.write_oam_address(index * 4 + 1, *tile);
+        }
+
+        write_register(&mut ppu, 0x01, 0x18);
+        clock_frame(&mut ppu);
+
+        while ppu.scanline() < 120 {
+            ppu.clock();
+        }
+
+        ppu.read(0x02, true) & 0x20 > 0
+    }
+
+    #[test]
+    fn it_sets_sprite_overflow_with_the_evaluation_bug() {
+        let mut sprites = vec![(0xf0, 0); 64];
+
+        for sprite in sprites.iter_mut().take(8) {
+            *sprite = (20, 0);
+        }
+        assert!(!sprite_overflow_after_frame(&sprites));
+
+        sprites[8] = (20, 0);
+        assert!(sprite_overflow_after_frame(&sprites));
+
+        // after 8 sprites a miss also moves to the next byte, so the tile
+        // index of sprite 9 is compared as if it was a Y coordinate
+        sprites[8] = (0xf0, 0);
+        sprites[9] = (0xf0, 20);
+        assert!(sprite_overflow_after_frame(&sprites));
+
+        // ...and a real 9th sprite can be missed the same way
+        sprites[9] = (0xf0, 0);
+        sprites[10] = (20, 0);
+        assert!(!sprite_overflow_after_frame(&sprites));
+    }
+
+    #[test]
+    fn it_can_draw_more_than_8_sprites_per_scanline() {
+        let mut rom = nrom_rom(&[]);
+        // tile 1 is a solid block of color 1
+        write_chr(&mut rom, 0x0010, &[0xff; 8]);
+
+        let mut ppu = PPU::new(cartridge(&rom));
+
+        for index in 0..64 {
+            let (y, x) = if index < 9 {
+                (20, index * 16)
+            } else {
+                (0xf0, 0)
+            };
+            ppu.write_oam_address(index * 4, y);
+            ppu.write_oam_address(index * 4 + 1, 1);
+            ppu.write_oam_address(index * 4 + 3, x as u8);
+        }
+
+        write_vram(&mut ppu, 0x3f11, 0x16);
+        write_register(&mut ppu, 0x01, 0x1e);
+        clock_frame(&mut ppu);
+
+        assert_eq!(ppu.frame().get_pixel(7 * 16 + 4, 24), 0x16);
+        assert_ne!(ppu.frame().get_pixel(8 * 16 + 4, 24), 0x16);
+
+        ppu.set_no_sprite_limit(true);
+        clock_frame(&mut ppu);
+
+        assert_eq!(ppu.frame().get_pixel(8 * 16 + 4, 24), 0x16);
+        assert!(ppu.read(0x02, true) & 0x20 > 0);
+    }
+
+    #[test]
+    fn it_can_hide_layers_without_changing_sprite0_hit() {
+        let mut rom = nrom_rom(&[]);
+        // tile 0 is a solid block of color 1, used by both layers
+        write_chr(&mut rom, 0x0000, &[0xff; 8]);
+
+        let mut ppu = PPU::new(cartridge(&rom));
+        ppu.write_oam_address(0, 20);
+        ppu.write_oam_address(3, 20);
+        for index in 1..64 {
+            ppu.write_oam_address(index * 4, 0xf0);
+        }
+
+        write_vram(&mut ppu, 0x3f00, 0x0f);
+        write_vram(&mut ppu, 0x3f01, 0x21);
+        write_vram(&mut ppu, 0x3f11, 0x2a);
+        write_register(&mut ppu, 0x01, 0x18);
+
+        ppu.set_render_debug(RenderDebug {
+            hide_background: true,
+            hide_sprites: true,
+            ..RenderDebug::default()
+        });
+        clock_frame(&mut ppu);
+
+        assert_eq!(ppu.frame().get_pixel(24, 24), 0x0f);
+        assert!(ppu.read(0x02, true) & 0x40 > 0);
+
+        // the left column is clipped by PPUMASK unless overridden
+        ppu.set_render_debug(RenderDebug::default());
+        clock_frame(&mut ppu);
+
+        assert_eq!(ppu.frame().get_pixel(2, 30), 0x0f);
+        assert_eq!(ppu.frame().get_pixel(24, 24), 0x2a);
+
+        ppu.set_render_debug(RenderDebug {
+            show_left_column: true,
+            highlight_sprite0_hit: true,
+            ..RenderDebug::default()
+        });
+        clock_frame(&mut ppu);
+
+        assert_eq!(ppu.frame().get_pixel(2, 30), 0x21);
+        assert_eq!(ppu.frame().get_pixel(24, 24), 0x16);
+        assert_eq!(ppu.frame().get_pixel(24, 30), 0x21);
+    }
+
+    #[test]
+    fn it_renders_the_nametable_viewer() {
+        let mut rom = nrom_rom(&[]);
+        // vertical mirroring, tile 1 is a solid block of color 1
+        rom[6] = 0x01;
+        write_chr(&mut rom, 0x0010, &[0xff; 8]);
+
+        let mut ppu = PPU::new(cartridge(&rom));
+        write_vram(&mut ppu, 0x2021, 0x01);
+        write_vram(&mut ppu, 0x23c0, 0x02);
+        write_vram(&mut ppu, 0x3f00, 0x0f);
+        write_vram(&mut ppu, 0x3f09, 0x16);
+        write_register(&mut ppu, 0x00, 0x00);
+        write_register(&mut ppu, 0x05, 16);
+        write_register(&mut ppu, 0x05, 0);
+
+        let info = ppu.debug_nametable_tile_info(8, 8 + 240);
+        assert_eq!(info.nametable, 2);
+        assert_eq!(info.tile_id, 0x01);
+        assert_eq!(info.address, 0x2821);
+        assert_eq!(info.attribute_address, 0x2bc0);
+        assert_eq!(info.palette, 0x02);
+
+        ppu.set_debug_nametable_screen();
+
+        let pixel = |ppu: &PPU, x: usize, y: usize| {
+            let pos = (y * 512 + x) * 4;
+            let image = ppu.screen_debug_nametable.image();
+            (image[pos], image[pos + 1], image[pos + 2])
+        };
+
+        let color = ppu.palette().color(0x16);
+        let backdrop = ppu.palette().color(0x0f);
+        assert_eq!(pixel(&ppu, 12, 12), color);
+        assert_eq!(pixel(&ppu, 12, 252), color);
+        assert_eq!(pixel(&ppu, 268, 12), backdrop);
+
+        // the viewport starts at the scroll position
+        assert_eq!(pixel(&ppu, 16, 100), (255, 0, 255));
+        assert_eq!(pixel(&ppu, 16 + 255, 100), (255, 0, 255));
+        assert_eq!(pixel(&ppu, 100, 239), (255, 0, 255));
+        assert_eq!(pixel(&ppu, 15, 100), backdrop);
+    }
+
+    #[test]
+    fn it_renders_the_sprite_viewer() {
+        let mut rom = nrom_rom(&[]);
+        // tile $13 has its left column set, tile $12 is empty
+        write_chr(&mut rom, 0x1130, &[0x80; 8]);
+
+        let mut ppu = PPU::new(cartridge(&rom));
+        for index in 0..64 {
+            ppu.write_oam_address(index * 4, 0xf0);
+        }
+        ppu.write_oam_address(4, 30);
+        ppu.write_oam_address(5, 0x13);
+        ppu.write_oam_address(6, 0x61);
+        ppu.write_oam_address(7, 40);
+
+        write_vram(&mut ppu, 0x3f00, 0x0f);
+        write_vram(&mut ppu, 0x3f15, 0x16);
+        // 8x16 sprites
+        write_register(&mut ppu, 0x00, 0x20);
+
+        let info = ppu.debug_sprite_info(1);
+        assert_eq!((info.x, info.y, info.tile, info.palette), (40, 30, 0x13, 1));
+        assert!(info.behind_background && info.flip_horizontal && !info.flip_vertical);
+        assert_eq!((info.pattern_address, info.height), (0x1120, 16));
+
+        ppu.set_debug_sprites_screen();
+
+        let pixel = |ppu: &PPU, x: usize, y: usize| {
+            let pos = (y * 64 + x) * 4;
+            let image = ppu.screen_debug_sprites.image();
+            (image[pos], image[pos + 1], image[pos + 2])
+        };
+
+        // the bottom half is tile $13, flipped to the right column
+        assert_eq!(pixel(&ppu, 8 + 7, 8), ppu.palette().color(0x16));
+        assert_eq!(pixel(&ppu, 8, 8), ppu.palette().color(0x0f));
+        assert_eq!(pixel(&ppu, 8 + 7, 0), ppu.palette().color(0x0f));
+
+        ppu.set_render_debug(RenderDebug {
+            sprite_boxes: true,
+            ..RenderDebug::default()
+        });
+        clock_frame(&mut ppu);
+
+        let image = ppu.screen().image();
+        let pos = (31 * NES_WIDTH_SIZE + 44) * 4;
+        assert_eq!((image[pos], image[pos + 1], image[pos + 2]), (0, 255, 0));
+        let pos = (46 * NES_WIDTH_SIZE + 47) * 4;
+        assert_eq!((image[pos], image[pos + 1], image[pos + 2]), (0, 255, 0));
+        let pos = (40 * NES_WIDTH_SIZE + 44) * 4;
+        assert_ne!((image[pos], image[pos + 1], image[pos + 2]), (0, 255, 0));
+    }
+
+    #[test]
+    fn it_can_edit_palette_ram_through_its_mirrors() {
+        let mut ppu = PPU::new(nrom_cartridge());
+
+        ppu.debug_write_palette(0x05, 0x16);
+        ppu.debug_write_palette(0x10, 0x2a);
+
+        assert_eq!(ppu.debug_read_palette(0x05), 0x16);
+        assert_eq!(ppu.debug_read_palette(0x00), 0x2a);
+        assert_eq!(ppu.debug_read_palette(0x10), 0x2a);
+        assert_eq!(ppu.get_color(1, 1), ppu.palette().color(0x16));
+    }
+
+    #[test]
+    fn it_records_register_accesses_and_nmi_by_dot() {
+        let mut rom = nrom_rom(&[
+            0xa9, 0x80, // LDA #$80
+            0x8d, 0x00, 0x20, // STA $2000
+            0x4c, 0x05, 0x80, // JMP $8005
+        ]);
+        write_prg(&mut rom, 0x8010, &[0x40]); // RTI
+        write_prg(&mut rom, 0xfffa, &[0x10, 0x80, 0x00, 0x80, 0x10, 0x80]);
+
+        let mut bus = Bus::new_from_array(&rom).unwrap();
+        bus.ppu.lock().unwrap().set_event_recording(true);
+        bus.reset();
+        bus.clock_until_frame_done();
+
+        let mut ppu = bus.ppu.lock().unwrap();
+        let events = ppu.last_frame_events().to_vec();
+
+        let write = events
+            .iter()
+            .find(|event| event.kind == PPUEventKind::RegisterWrite)
+            .unwrap();
+        assert_eq!(
+            (write.address, write.value, write.pc),
+            (0x2000, 0x80, 0x8002)
+        );
+        assert_eq!(write.scanline, 0);
+
+        let nmi = events
+            .iter()
+            .find(|event| event.kind == PPUEventKind::Nmi)
+            .unwrap();
+        assert_eq!((nmi.scanline, nmi.cycle), (241, 3));
+        assert_eq!(nmi.pc, 0x8005);
+
+        ppu.set_debug_events_screen();
+        assert_eq!(ppu.screen_debug_events.height(), 262);
+        assert_eq!(ppu.debug_event_at(4, 240), Some(*nmi));
+        assert_eq!(ppu.debug_event_at(100, 100), None);
+    }
+
+    fn clock_until(ppu: &mut PPU, scanline: i32, cycle: i32) {
+        while ppu.scanline() != scanline || ppu.cycle() != cycle {
+            ppu.clock();
+        }
+    }
+
+    #[test]
+    fn it_delays_the_second_ppuaddr_write() {
+        let mut ppu = PPU::new(nrom_cartridge());
+
+        write_register(&mut ppu, 0x06, 0x20);
+        write_register(&mut ppu, 0x06, 0x00);
+
+        ppu.write(0x06, 0x28);
+        ppu.write(0x06, 0x00);
+        ppu.write(0x07, 0x55);
+        for _ in 0..3 {
+            ppu.clock();
+        }
+        ppu.write(0x07, 0x66);
+
+        assert_eq!(ppu.ppu_read(0x2000, true), 0x55);
+        assert_eq!(ppu.ppu_read(0x2800, true), 0x66);
+    }
+
+    #[test]
+    fn it_glitches_ppuaddr_writes_landing_on_scroll_increments() {
+        let mut ppu = PPU::new(nrom_cartridge());
+        write_register(&mut ppu, 0x01, 0x08);
+        clock_frame(&mut ppu);
+
+        // the copy lands on dot 257, together with the horizontal copy
+        clock_until(&mut ppu, 10, 255);
+        ppu.write(0x06, 0x3f);
+        ppu.write(0x06, 0xff);
+        clock_until(&mut ppu, 10, 258);
+        assert_eq!(ppu.vram_address(), 0x343f);
+
+        // the copy lands on a coarse X increment
+        clock_until(&mut ppu, 20, 14);
+        ppu.write(0x06, 0x27);
+        ppu.write(0x06, 0xe2);
+        let before = ppu.vram_address();
+        clock_until(&mut ppu, 20, 17);
+        let incremented = (before & !0x1f) | ((before + 1) & 0x1f);
+        assert_eq!(
+            ppu.vram_address(),
+            0x27e2 & !0x41f | (incremented & 0x27e2 & 0x41f)
+        );
+    }
+
+    #[test]
+    fn it_glitches_ppuaddr_writes_landing_on_the_y_increment() {
+        let rendering_ppu = || {
+            let mut ppu = PPU::new(nrom_cartridge());
+            write_register(&mut ppu, 0x01, 0x08);
+            clock_frame(&mut ppu);
+            clock_until(&mut ppu, 10, 254);
+            ppu
+        };
+
+        // where dot 256 takes v without the write
+        let mut ppu = rendering_ppu();
+        clock_until(&mut ppu, 10, 257);
+        let incremented = ppu.vram_address();
+
+        // the copy lands on dot 256, where both X and Y are incremented
+        let mut ppu = rendering_ppu();
+        ppu.write(0x06, 0x27);
+        ppu.write(0x06, 0xe2);
+        clock_until(&mut ppu, 10, 257);
+        assert_eq!(ppu.vram_address(), incremented & 0x27e2);
+        assert_ne!(incremented & 0x7be0, 0x27e2 & 0x7be0);
+    }
+
+    #[test]
+    fn it_increments_scroll_on_ppudata_access_while_rendering() {
+        let mut ppu = PPU::new(nrom_cartridge());
+        write_register(&mut ppu, 0x01, 0x08);
+        clock_frame(&mut ppu);
+
+        clock_until(&mut ppu, 10, 100);
+        let before = ppu.vram_address();
+        ppu.write(0x07, 0x00);
+
+        assert_eq!(ppu.vram_address(), before + 0x1000 + 1);
+    }
+
+    // read PPUSTATUS when the PPU is about to run the given dot of the
+    // VBL scanline, and report the flag and whether an NMI followed
+    fn read_status_near_vblank(cycle: i32) -> (bool, bool) {
+        let mut ppu = PPU::new(nrom_cartridge());
+        write_register(&mut ppu, 0x00, 0x80);
+
+        clock_until(&mut ppu, 241, cycle);
+        let status = ppu.read(0x02, false);
+
+        let mut nmi = ppu.call_nmi;
+        for _ in 0..10 {
+            ppu.clock();
+            nmi |= ppu.call_nmi;
+        }
+
+        (status & 0x80 > 0, nmi)
+    }
+
+    #[test]
+    fn it_races_ppustatus_reads_with_vblank() {
+        assert_eq!(read_status_near_vblank(0), (false, true));
+        assert_eq!(read_status_near_vblank(1), (false, false));
+        assert_eq!(read_status_near_vblank(2), (true, false));
+        assert_eq!(read_status_near_vblank(3), (true, false));
+        assert_eq!(read_status_near_vblank(4), (true, true));
+    }
+
+    #[test]
+    fn it_fires_nmi_when_enabled_during_vblank() {
+        let mut ppu = PPU::new(nrom_cartridge());
+
+        clock_until(&mut ppu, 241, 100);
+        assert!(!ppu.call_nmi);
+
+        write_register(&mut ppu, 0x00, 0x80);
+        assert!(ppu.call_nmi);
+        ppu.call_nmi = false;
+
+        // toggling NMI while VBL stays set fires again
+        write_register(&mut ppu, 0x00, 0x00);
+        write_register(&mut ppu, 0x00, 0x80);
+        assert!(ppu.call_nmi);
+        ppu.call_nmi = false;
+
+        // disabling NMI right after VBL is set cancels it
+        clock_frame(&mut ppu);
+        clock_until(&mut ppu, 241, 2);
+        ppu.write(0x00, 0x00);
+        for _ in 0..10 {
+            ppu.clock();
+        }
+        assert!(!ppu.call_nmi);
+    }
+}
@@ -0,0 +1,221 @@
+mod test_utils;
+
+#[cfg(test)]
+mod processor_tests {
+    use crate::test_utils::RAM;
+    use nesrs::cpu::types::*;
+    use nesrs::cpu::*;
+    use serde::Deserialize;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // Single-step tests from https://github.com/TomHarte/ProcessorTests,
+    // one JSON file per opcode. Point PROCESSOR_TESTS_DIR to the `6502/v1`
+    // directory, or put it in tests/ProcessorTests/6502/v1.
+    const DEFAULT_DIR: &str = "tests/ProcessorTests/6502/v1";
+
+    #[derive(Deserialize)]
+    struct TestCase {
+        name: String,
+        initial: State,
+        #[serde(rename = "final")]
+        final_state: State,
+        cycles: Vec<(u16, u8, String)>,
+    }
+
+    #[derive(Deserialize)]
+    struct State {
+        pc: u16,
+        s: u8,
+        a: u8,
+        x: u8,
+        y: u8,
+        p: u8,
+        ram: Vec<(u16, u8)>,
+    }
+
+    // B and U only exist on the stack, so they're left out of P
+    fn registers(state: &State) -> String {
+        format!(
+            "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+            state.pc,
+            state.a,
+            state.x,
+            state.y,
+            state.p & 0xcf,
+            state.s
+        )
+    }
+
+    // Run one test case and describe every mismatch
+    fn run_case(case: &TestCase, variant: CpuVariant) -> Vec<String> {
+        let mut memory = RAM {
+            ram: vec![0; 0x10000],
+        };
+        for (address, value) in case.initial.ram.iter() {
+            memory.ram[*address as usize] = *value;
+        }
+
+        let mut cpu = CPU::new_with_variant(variant);
+        cpu.set_registers(CPURegisters {
+            a: case.initial.a,
+            x: case.initial.x,
+            y: case.initial.y,
+            sp: case.initial.s,
+            p: StatusFlag::from_bits_truncate(case.initial.p),
+            pc: case.initial.pc,
+        });
+        cpu.enable_trace(case.cycles.len() + 8);
+
+        cpu.clock(&mut memory);
+        while !cpu.done() {
+            cpu.clock(&mut memory);
+        }
+
+        let mut errors = vec![];
+
+        let result = State {
+            pc: cpu.regs.pc,
+            s: cpu.regs.sp,
+            a: cpu.regs.a,
+            x: cpu.regs.x,
+            y: cpu.regs.y,
+            p: cpu.regs.p.bits(),
+            ram: vec![],
+        };
+        if registers(&result) != registers(&case.final_state) {
+            errors.push(format!(
+                "registers: expected {}, got {}",
+                registers(&case.final_state),
+                registers(&result)
+            ));
+        }
+
+        for (address, value) in case.final_state.ram.iter() {
+            let actual = memory.ram[*address as usize];
+            if actual != *value {
+                errors.push(format!(
+                    "ram ${:04X}: expected {:02X}, got {:02X}",
+                    address, value, actual
+                ));
+            }
+        }
+
+        let expected = case
+            .cycles
+            .iter()
+            .map(|(address, value, kind)| {
+                let kind = if kind == "read" { "R" } else { "W" };
+                format!("{:04X} {:02X} {}", address, value, kind)
+            })
+            .collect::<Vec<_>>();
+        let actual = cpu
+            .trace()
+            .unwrap()
+            .cycles()
+            .map(|cycle| {
+                let kind = if cycle.is_read { "R" } else { "W" };
+                format!("{:04X} {:02X} {}", cycle.address, cycle.value, kind)
+            })
+            .collect::<Vec<_>>();
+        if expected != actual {
+            errors.push(format!(
+                "cycles: expected [{}], got [{}]",
+                expected.join(", "),
+                actual.join(", ")
+            ));
+        }
+
+        errors
+            .into_iter()
+            .map(|error| format!("{}: {}", case.name, error))
+            .collect()
+    }
+
+    // The tests come from an NMOS 6502, which only differs from the 2A03
+    // in having a decimal mode. Cases running ADC or SBC with D set go to
+    // an NMOS 6502, everything else to the 2A03.
+    fn run_file(path: &PathBuf, opcode: u8) -> Result<Vec<String>, String> {
+        let json = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let cases: Vec<TestCase> = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+
+        let (_, instruction, _) = &OPCODE_TABLE[opcode as usize];
+        let uses_decimal_mode = matches!(instruction, Opcode::Adc | Opcode::Sbc);
+
+        Ok(cases
+            .iter()
+            .flat_map(|case| {
+                let is_decimal = case.initial.p & StatusFlag::D.bits() != 0;
+                let variant = if uses_decimal_mode && is_decimal {
+                    CpuVariant::Nmos6502
+                } else {
+                    CpuVariant::Ricoh2A03
+                };
+
+                run_case(case, variant)
+            })
+            .collect())
+    }
+
+    // the opcodes the CPU doesn't implement are left out
+    fn is_supported(opcode: u8) -> bool {
+        let (_, instruction, _) = &OPCODE_TABLE[opcode as usize];
+        !matches!(instruction, Opcode::Xxx)
+    }
+
+    #[test]
+    fn it_runs_a_single_step_test() {
+        let json = r#"[{
+            "name": "b5 f0 20",
+            "initial": { "pc": 32768, "s": 253, "a": 0, "x": 32, "y": 0, "p": 36,
+                         "ram": [[32768, 181], [32769, 240], [240, 1], [16, 66]] },
+            "final": { "pc": 32770, "s": 253, "a": 66, "x": 32, "y": 0, "p": 36,
+                       "ram": [[32768, 181], [32769, 240], [240, 1], [16, 66]] },
+            "cycles": [[32768, 181, "read"], [32769, 240, "read"],
+                       [240, 1, "read"], [16, 66, "read"]]
+        }]"#;
+        let cases: Vec<TestCase> = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            run_case(&cases[0], CpuVariant::Ricoh2A03),
+            Vec::<String>::new()
+        );
+
+        // a wrong expectation gets reported
+        let mut case = cases.into_iter().next().unwrap();
+        case.final_state.a = 0x43;
+        case.cycles.pop();
+        assert_eq!(run_case(&case, CpuVariant::Ricoh2A03).len(), 2);
+    }
+
+    #[test]
+    fn it_passes_processor_tests() {
+        let dir = PathBuf::from(
+            std::env::var("PROCESSOR_TESTS_DIR").unwrap_or_else(|_| DEFAULT_DIR.to_string()),
+        );
+
+        if !dir.is_dir() {
+            println!("{} not found, skipping", dir.display());
+            return;
+        }
+
+        let mut failures = vec![];
+
+        for opcode in 0..=0xffu8 {
+            let path = dir.join(format!("{:02x}.json", opcode));
+            if !is_supported(opcode) || !path.is_file() {
+                continue;
+            }
+
+            match run_file(&path, opcode) {
+                Ok(errors) => failures.extend(errors),
+                Err(error) => failures.push(format!("{}: {}", path.display(), error)),
+            }
+        }
+
+        for failure in failures.iter().take(50) {
+            println!("{}", failure);
+        }
+
+        assert!(failures.is_empty(), "{} mismatches", failures.len());
+    }
+}
@@ -1,3 +1,7 @@
+// every test crate includes this, and only uses part of it
+#![allow(dead_code)]
+
+use nesrs::bus::Bus;
 use nesrs::memory::*;
 
 pub struct RAM {
@@ -21,3 +25,40 @@ impl Memory for RAM {
         self.ram[address] = value;
     }
 }
+
+// PRG ROM starts after the iNES header, CHR ROM after 16K of PRG ROM
+const PRG_START: usize = 16;
+const CHR_START: usize = PRG_START + 0x4000;
+
+/// An NROM image with 16K of PRG ROM and 8K of CHR ROM. `program` is
+/// mapped at $8000, where every vector points.
+pub fn nrom_rom(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; CHR_START + 0x2000];
+    rom[0..4].copy_from_slice(b"NES\x1a");
+    rom[4] = 1;
+    rom[5] = 1;
+
+    write_prg(&mut rom, 0x8000, program);
+    write_prg(&mut rom, 0xfffa, &[0x00, 0x80, 0x00, 0x80, 0x00, 0x80]);
+
+    rom
+}
+
+/// Put `bytes` at the CPU `address` of an NROM image
+pub fn write_prg(rom: &mut [u8], address: u16, bytes: &[u8]) {
+    let start = PRG_START + (address as usize & 0x3fff);
+    rom[start..start + bytes.len()].copy_from_slice(bytes);
+}
+
+/// Put `bytes` at the PPU `address` of an NROM image
+pub fn write_chr(rom: &mut [u8], address: u16, bytes: &[u8]) {
+    let start = CHR_START + (address as usize & 0x1fff);
+    rom[start..start + bytes.len()].copy_from_slice(bytes);
+}
+
+/// A reset bus running `program` from $8000
+pub fn nrom_bus(program: &[u8]) -> Bus {
+    let mut bus = Bus::new_from_array(&nrom_rom(program)).unwrap();
+    bus.reset();
+    bus
+}